@@ -0,0 +1,75 @@
+use std::{
+    env::current_dir,
+    path::{Component, Path, PathBuf},
+};
+
+use colored::Colorize;
+use redstone_common::model::{
+    backup::{get_index_file_for_path, IndexFile},
+    fs_tree::ScanOptions,
+    ignore_rules::check_ignore,
+    Result,
+};
+
+use super::models::CheckIgnoreArgs;
+
+pub fn run_check_ignore_cmd(check_ignore_args: CheckIgnoreArgs) -> Result<()> {
+    let root = current_dir()?;
+    let path = normalize_path(&root.join(&check_ignore_args.path));
+    // outside of a backup the rules are explained with the default settings
+    let index_file_path = get_index_file_for_path(&root);
+    let scan_options = match index_file_path.exists() {
+        true => IndexFile::from_file(&index_file_path)?
+            .config
+            .scan_options(),
+        false => ScanOptions::default(),
+    };
+    let displayed_path = check_ignore_args.path.display().to_string();
+
+    let Some(matched) = check_ignore(&root, &path, &scan_options)? else {
+        println!("{} is not ignored", displayed_path.green());
+        return Ok(());
+    };
+    let mut source = matched
+        .file
+        .map(|file| {
+            file.strip_prefix(&root)
+                .unwrap_or(&file)
+                .display()
+                .to_string()
+        })
+        .unwrap_or_default();
+    if let Some(line) = matched.line {
+        source += &format!(":{line}");
+    }
+
+    let message = if matched.is_negated {
+        format!("{} is included by", displayed_path.green())
+    } else if matched.path == path {
+        format!("{} is ignored by", displayed_path.red())
+    } else {
+        let folder = matched.path.strip_prefix(&root).unwrap_or(&matched.path);
+        format!(
+            "{} is ignored because its folder {} is ignored by",
+            displayed_path.red(),
+            folder.display()
+        )
+    };
+    println!("{message} \"{}\" in {source}", matched.rule);
+    Ok(())
+}
+
+/// Resolves `.` and `..` without touching the file system, as the path may not exist.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
@@ -9,6 +9,7 @@ use redstone_common::{
             clone::CloneRequest, ConfirmationRequest, IpcMessage, IpcMessageRequest,
             IpcMessageRequestType, IpcMessageResponse,
         },
+        tcp::TransferOptions,
         DomainError, RedstoneError, Result,
     },
 };
@@ -20,17 +21,25 @@ use super::{models::CloneArgs, progress_bar::handle_progress_bar};
 pub fn run_clone_cmd(clone_args: CloneArgs) -> Result<()> {
     assert_configuration_and_authentication()?;
     let path = current_dir()?;
-    let backup_name = clone_args.backup_name;
+    let backup_name = clone_args.backup_name.clone();
     let index_file_path = get_index_file_for_path(&path);
     if index_file_path.exists() {
-        let path = path.to_str().unwrap().into();
+        let path = path.to_string_lossy().into();
         return Err(RedstoneError::DomainError(
             DomainError::BackupAlreadyExists(path),
         ));
     }
 
     let request = IpcMessage::Request(IpcMessageRequest {
-        message: IpcMessageRequestType::CloneRequest(CloneRequest { path, backup_name }),
+        message: IpcMessageRequestType::CloneRequest(CloneRequest {
+            path,
+            backup_name,
+            wait: clone_args.wait,
+            transfer: TransferOptions {
+                connections: clone_args.connections,
+                ..Default::default()
+            },
+        }),
     });
 
     let mut connection = stablish_connection()?;
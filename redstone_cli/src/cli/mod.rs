@@ -1,6 +1,7 @@
 pub mod models;
 
 mod auth;
+mod check_ignore;
 mod clone;
 mod progress_bar;
 mod pull;
@@ -20,13 +21,16 @@ pub fn input() -> redstone_common::model::Result<()> {
             let client = RedstoneBlockingClient::new();
             auth::run_auth_cmd(client)
         }
+        Commands::CheckIgnore(check_ignore_args) => {
+            check_ignore::run_check_ignore_cmd(check_ignore_args)
+        }
         Commands::Clone(clone_args) => clone::run_clone_cmd(clone_args),
-        Commands::Pull => pull::run_pull_cmd(),
-        Commands::Push => push::run_push_cmd(),
+        Commands::Pull(pull_args) => pull::run_pull_cmd(pull_args),
+        Commands::Push(push_args) => push::run_push_cmd(push_args),
         Commands::ServerConfig(set_server_args) => {
             server_config::run_server_config(set_server_args)
         }
-        Commands::Status => status::run_status_cmd(),
+        Commands::Status(status_args) => status::run_status_cmd(status_args),
         Commands::Track(track_args) => track::run_track_cmd(track_args),
     }
 }
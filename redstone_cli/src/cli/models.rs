@@ -1,4 +1,11 @@
+use std::path::PathBuf;
+
 use clap::{Args, Parser, Subcommand};
+use redstone_common::{
+    constants::{DEFAULT_TRANSFER_CONNECTIONS, DEFAULT_UPLOAD_WINDOW},
+    model::{digest::HashAlgorithm, fs_tree::SymlinkPolicy, tcp::TransferOptions},
+    util::human_readable_to_bytes,
+};
 
 #[derive(Debug, Parser)]
 #[clap(author="Pedro Vietro", version="0.0.1", about="Redstone is a Self-hosted CLI backup tool ", long_about = None)]
@@ -12,11 +19,14 @@ pub enum Commands {
     /// Authenticate by using your email and password
     Auth,
 
+    /// Explain which ignore rule, if any, excludes or re-includes a path
+    CheckIgnore(CheckIgnoreArgs),
+
     /// Clone a backup by providing the backup name
     Clone(CloneArgs),
 
     /// Push the changes made to the server
-    Push,
+    Push(PushArgs),
 
     /// Recursively scan a directory and create a backup with all the files scanned
     Track(TrackArgs),
@@ -25,16 +35,77 @@ pub enum Commands {
     ServerConfig(ServerConfigArgs),
 
     /// Check for changes in the current bakcup
-    Status,
+    Status(StatusArgs),
 
     /// Pull the latest changes from the server
-    Pull,
+    Pull(PullArgs),
+}
+
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct CheckIgnoreArgs {
+    pub path: PathBuf,
 }
 
 #[derive(Debug, Args)]
 #[clap(args_conflicts_with_subcommands = true)]
 pub struct CloneArgs {
     pub backup_name: String,
+
+    #[clap(
+        long,
+        help = "Wait for other operations on the backup to finish, instead of failing"
+    )]
+    pub wait: bool,
+
+    #[clap(
+        long,
+        default_value_t = DEFAULT_TRANSFER_CONNECTIONS,
+        value_parser = parse_connections,
+        help = "Amount of connections files are transferred over in parallel"
+    )]
+    pub connections: usize,
+}
+
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct PushArgs {
+    #[clap(long, help = "Rehash every file instead of trusting the stat cache")]
+    pub paranoid: bool,
+
+    #[clap(
+        long,
+        help = "Wait for other operations on the backup to finish, instead of failing"
+    )]
+    pub wait: bool,
+
+    #[clap(flatten)]
+    pub transfer: TransferArgs,
+}
+
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct PullArgs {
+    #[clap(
+        long,
+        help = "Wait for other operations on the backup to finish, instead of failing"
+    )]
+    pub wait: bool,
+
+    #[clap(
+        long,
+        default_value_t = DEFAULT_TRANSFER_CONNECTIONS,
+        value_parser = parse_connections,
+        help = "Amount of connections files are transferred over in parallel"
+    )]
+    pub connections: usize,
+}
+
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct StatusArgs {
+    #[clap(long, help = "Rehash every file instead of trusting the stat cache")]
+    pub paranoid: bool,
 }
 
 #[derive(Debug, Args)]
@@ -67,4 +138,130 @@ pub struct TrackArgs {
 
     #[clap(long, short = 'd')]
     pub detached: bool,
+
+    #[clap(
+        long,
+        name = "hashing-threads",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Amount of threads used to hash files (defaults to the number of CPUs)"
+    )]
+    pub hashing_threads: Option<usize>,
+
+    #[clap(long, help = "Store and restore the files' owner and group")]
+    pub preserve_ownership: bool,
+
+    #[clap(long, help = "Store and restore the files' extended attributes")]
+    pub preserve_xattrs: bool,
+
+    #[clap(
+        long,
+        default_value = "store",
+        help = "How symlinks are backed up: store (the link itself), follow or skip"
+    )]
+    pub symlinks: SymlinkPolicy,
+
+    #[clap(
+        long,
+        name = "hash-algorithm",
+        default_value = "sha256",
+        help = "Algorithm used to hash the files' content: sha256 or blake3"
+    )]
+    pub hash_algorithm: HashAlgorithm,
+
+    #[clap(
+        long,
+        value_parser = parse_size,
+        help = "Exclude files bigger than this size, such as 500MB or 2G"
+    )]
+    pub max_file_size: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Exclude files which weren't modified in this amount of days"
+    )]
+    pub max_age_days: Option<u64>,
+
+    #[clap(long, help = "Exclude folders tagged as caches by a CACHEDIR.TAG file")]
+    pub exclude_caches: bool,
+
+    #[clap(long, help = "Don't descend into folders on other file systems")]
+    pub one_file_system: bool,
+
+    #[clap(
+        long,
+        help = "Also honor .gitignore, .ignore and git's excludes (.rsignore takes precedence)"
+    )]
+    pub git_ignore: bool,
+
+    #[clap(
+        long,
+        help = "Only back up the paths matching this pattern, can be repeated (see also .rsinclude)"
+    )]
+    pub include: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Fail when a file can't be read, instead of skipping it and reporting it"
+    )]
+    pub strict: bool,
+
+    #[clap(
+        long,
+        help = "Wait for other operations on the backup to finish, instead of failing"
+    )]
+    pub wait: bool,
+
+    #[clap(flatten)]
+    pub transfer: TransferArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct TransferArgs {
+    #[clap(
+        long,
+        default_value_t = DEFAULT_UPLOAD_WINDOW,
+        value_parser = parse_window,
+        help = "Amount of chunks sent before waiting for the server to acknowledge them"
+    )]
+    pub upload_window: usize,
+
+    #[clap(
+        long,
+        default_value_t = DEFAULT_TRANSFER_CONNECTIONS,
+        value_parser = parse_connections,
+        help = "Amount of connections files are transferred over in parallel"
+    )]
+    pub connections: usize,
+}
+
+impl From<TransferArgs> for TransferOptions {
+    fn from(args: TransferArgs) -> Self {
+        Self {
+            upload_window: args.upload_window,
+            connections: args.connections,
+        }
+    }
+}
+
+fn parse_size(size: &str) -> Result<u64, String> {
+    human_readable_to_bytes(size).ok_or(format!(
+        "Invalid size \"{size}\", expected an amount of bytes optionally followed by KB, MB, GB or TB"
+    ))
+}
+
+fn parse_window(window: &str) -> Result<usize, String> {
+    parse_positive(window, "window", "chunks")
+}
+
+fn parse_connections(connections: &str) -> Result<usize, String> {
+    parse_positive(connections, "amount of connections", "connections")
+}
+
+fn parse_positive(value: &str, name: &str, unit: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(value) if value > 0 => Ok(value),
+        _ => Err(format!(
+            "Invalid {name} \"{value}\", expected a positive amount of {unit}"
+        )),
+    }
 }
@@ -9,26 +9,34 @@ use redstone_common::{
             pull::PullRequest, ConfirmationRequest, IpcMessage, IpcMessageRequest,
             IpcMessageRequestType, IpcMessageResponse,
         },
+        tcp::TransferOptions,
         DomainError, RedstoneError, Result,
     },
 };
 
 use crate::{ipc::socket::stablish_connection, utils::handle_confirmation_request};
 
-use super::progress_bar::handle_progress_bar;
+use super::{models::PullArgs, progress_bar::handle_progress_bar};
 
-pub fn run_pull_cmd() -> Result<()> {
+pub fn run_pull_cmd(pull_args: PullArgs) -> Result<()> {
     assert_configuration_and_authentication()?;
     let path = current_dir()?;
     let index_file_path = get_index_file_for_path(&path);
     if !index_file_path.exists() {
-        let path = path.to_str().unwrap().into();
+        let path = path.to_string_lossy().into();
         return Err(RedstoneError::DomainError(DomainError::BackupDoesntExist(
             path,
         )));
     }
     let request = IpcMessage::Request(IpcMessageRequest {
-        message: IpcMessageRequestType::PullRequest(PullRequest { path }),
+        message: IpcMessageRequestType::PullRequest(PullRequest {
+            path,
+            wait: pull_args.wait,
+            transfer: TransferOptions {
+                connections: pull_args.connections,
+                ..Default::default()
+            },
+        }),
     });
     let mut connection = stablish_connection()?;
     let received_message = send_and_receive(&mut connection, &request)?;
@@ -15,21 +15,26 @@ use redstone_common::{
 
 use crate::{ipc::socket::stablish_connection, utils::handle_confirmation_request};
 
-use super::progress_bar::handle_progress_bar;
+use super::{models::PushArgs, progress_bar::handle_progress_bar};
 
-pub fn run_push_cmd() -> Result<()> {
+pub fn run_push_cmd(push_args: PushArgs) -> Result<()> {
     assert_configuration_and_authentication()?;
     let path = current_dir()?;
     let index_file_path = get_index_file_for_path(&path);
     if !index_file_path.exists() {
-        let path = path.to_str().unwrap().into();
+        let path = path.to_string_lossy().into();
         return Err(RedstoneError::DomainError(DomainError::BackupDoesntExist(
             path,
         )));
     }
 
     let request = IpcMessage::Request(IpcMessageRequest {
-        message: IpcMessageRequestType::PushRequest(PushRequest { path }),
+        message: IpcMessageRequestType::PushRequest(PushRequest {
+            path,
+            paranoid: push_args.paranoid,
+            wait: push_args.wait,
+            transfer: push_args.transfer.into(),
+        }),
     });
     let mut connection = stablish_connection()?;
     let mut received_message = send_and_receive(&mut connection, &request)?;
@@ -3,23 +3,36 @@ use std::env::current_dir;
 use redstone_common::model::{
     backup::{get_index_file_for_path, IndexFile},
     fs_tree::FSTree,
+    journal::{get_journal_file_for_path, JournalEntry},
     DomainError, RedstoneError, Result,
 };
 
-pub fn run_status_cmd() -> Result<()> {
+use super::models::StatusArgs;
+
+pub fn run_status_cmd(status_args: StatusArgs) -> Result<()> {
     let path = current_dir()?;
     let index_file_path = get_index_file_for_path(&path);
     if !index_file_path.exists() {
-        let path = path.to_str().unwrap().into();
+        let path = path.to_string_lossy().into();
         return Err(RedstoneError::DomainError(DomainError::BackupDoesntExist(
             path,
         )));
     }
     let index_file = IndexFile::from_file(&index_file_path)?;
-    let current_fs_tree = FSTree::build(path, None)?;
+    if let Some(journal_entry) = JournalEntry::from_file(&get_journal_file_for_path(&path))? {
+        println!("{}\n", journal_entry.get_interrupted_message());
+    }
+    let mut scan_options = index_file.config.scan_options();
+    scan_options.paranoid = status_args.paranoid;
+    let mut current_fs_tree = FSTree::build_read_only(path, &scan_options)?;
+    current_fs_tree.keep_unscanned_files(&index_file.last_fs_tree);
     let diff = current_fs_tree.diff(&index_file.last_fs_tree)?;
 
-    println!("{}", diff.get_changes_message());
+    println!(
+        "{}{}",
+        diff.get_changes_message(),
+        current_fs_tree.get_skipped_message()
+    );
 
     Ok(())
 }
@@ -4,6 +4,7 @@ use redstone_common::{
     config::assert_configuration_and_authentication,
     ipc::send_and_receive,
     model::{
+        fs_tree::ExclusionRules,
         ipc::track::TrackRequest,
         ipc::{
             ConfirmationRequest, IpcMessage, IpcMessageRequest, IpcMessageRequestType,
@@ -26,6 +27,22 @@ pub fn run_track_cmd(track_args: TrackArgs) -> Result<()> {
         detatched: track_args.detached,
         sync_every: track_args.sync_every,
         watch: track_args.watch,
+        hashing_threads: track_args.hashing_threads,
+        preserve_ownership: track_args.preserve_ownership,
+        preserve_xattrs: track_args.preserve_xattrs,
+        symlinks: track_args.symlinks,
+        hash_algorithm: track_args.hash_algorithm,
+        exclusions: ExclusionRules {
+            max_file_size: track_args.max_file_size,
+            max_age_days: track_args.max_age_days,
+            exclude_caches: track_args.exclude_caches,
+            one_file_system: track_args.one_file_system,
+        },
+        git_ignore: track_args.git_ignore,
+        include: track_args.include,
+        strict: track_args.strict,
+        wait: track_args.wait,
+        transfer: track_args.transfer.into(),
     };
     let request = IpcMessage::Request(IpcMessageRequest {
         message: IpcMessageRequestType::TrackRequest(track_request),
@@ -37,6 +37,23 @@ pub fn get_home_dir() -> Result<PathBuf> {
     dirs::home_dir().ok_or(RedstoneError::NoHomeDir)
 }
 
+#[cfg(feature = "testing")]
+pub fn get_global_ignore_file() -> Result<PathBuf> {
+    let mut dir = std::env::temp_dir();
+    dir.push("test");
+    dir.push("ignore");
+    Ok(dir)
+}
+
+/// User-wide ignore rules, applied to every backup.
+#[cfg(not(feature = "testing"))]
+pub fn get_global_ignore_file() -> Result<PathBuf> {
+    let mut home_dir = get_home_dir()?;
+    home_dir.push(".redstone");
+    home_dir.push("ignore");
+    Ok(home_dir)
+}
+
 #[cfg(feature = "testing")]
 pub fn get_auth_dir() -> Result<PathBuf> {
     let mut dir = std::env::temp_dir();
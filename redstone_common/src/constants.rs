@@ -7,3 +7,9 @@ pub const IPC_BUFFER_SIZE: usize = 16384;
 pub const IPC_BUFFER_SIZE: usize = 8192;
 
 pub const TCP_FILE_CHUNK_SIZE: usize = 1024 * 500; // 500KB
+
+pub const DEFAULT_UPLOAD_WINDOW: usize = 8;
+
+pub const DEFAULT_TRANSFER_CONNECTIONS: usize = 4;
+
+pub const HASH_BUFFER_SIZE: usize = 1024 * 64; // 64KB
@@ -6,5 +6,7 @@ pub mod config;
 pub mod constants;
 pub mod ipc;
 pub mod model;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod util;
 pub mod web;
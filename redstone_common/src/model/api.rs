@@ -5,7 +5,10 @@ use std::path::PathBuf;
 use crate::web::api::get_api_base_url;
 
 use super::{
-    fs_tree::{FSTreeDiff, RSFile},
+    backup::SharedBackupConfig,
+    digest::HashAlgorithm,
+    file_metadata::FileMetadata,
+    fs_tree::{FSTreeDiff, FileKind, RSFile},
     Result,
 };
 
@@ -41,29 +44,34 @@ impl Endpoints {
 pub enum FileOperation {
     Add,
     Update,
+    UpdateMetadata,
+    Move,
     Remove,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct FileUploadRequest {
     pub path: String,
-    pub sha_256_digest: Option<String>,
+    #[serde(rename = "sha_256_digest")]
+    pub digest: Option<String>,
     pub operation: FileOperation,
     pub size: u64,
+    /// Previous path of the file, only set for `FileOperation::Move`.
+    pub from_path: Option<String>,
+    pub metadata: Option<FileMetadata>,
+    pub kind: FileKind,
 }
 
 impl FileUploadRequest {
-    pub fn new(
-        path: String,
-        sha_256_digest: Option<String>,
-        operation: FileOperation,
-        size: u64,
-    ) -> Self {
+    pub fn new(path: String, digest: Option<String>, operation: FileOperation, size: u64) -> Self {
         Self {
             path,
-            sha_256_digest,
+            digest,
             operation,
             size,
+            from_path: None,
+            metadata: None,
+            kind: FileKind::File,
         }
     }
 
@@ -74,9 +82,12 @@ impl FileUploadRequest {
             .clone()
             .map(|f| Self {
                 path: f.path.to_owned(),
-                sha_256_digest: Some(f.sha_256_digest.to_owned()),
+                digest: Some(f.digest.to_owned()),
                 operation: FileOperation::Add,
                 size: f.size,
+                from_path: None,
+                metadata: Some(f.metadata.to_owned()),
+                kind: f.kind.to_owned(),
             })
             .collect();
 
@@ -86,9 +97,27 @@ impl FileUploadRequest {
             .clone()
             .map(|f| Self {
                 path: f.path.to_owned(),
-                sha_256_digest: Some(f.sha_256_digest.to_owned()),
+                digest: Some(f.digest.to_owned()),
                 operation: FileOperation::Update,
                 size: f.size,
+                from_path: None,
+                metadata: Some(f.metadata.to_owned()),
+                kind: f.kind.to_owned(),
+            })
+            .collect();
+
+        let moved_files: Vec<Self> = diff
+            .moved_files
+            .iter()
+            .clone()
+            .map(|f| Self {
+                path: f.to.path.to_owned(),
+                digest: Some(f.to.digest.to_owned()),
+                operation: FileOperation::Move,
+                size: f.to.size,
+                from_path: Some(f.from.path.to_owned()),
+                metadata: Some(f.to.metadata.to_owned()),
+                kind: f.to.kind.to_owned(),
             })
             .collect();
 
@@ -98,13 +127,38 @@ impl FileUploadRequest {
             .clone()
             .map(|f| Self {
                 path: f.path.to_owned(),
-                sha_256_digest: None,
+                digest: None,
                 operation: FileOperation::Remove,
                 size: f.size,
+                from_path: None,
+                metadata: None,
+                kind: f.kind.to_owned(),
+            })
+            .collect();
+
+        let metadata_changed_files: Vec<Self> = diff
+            .metadata_changed_files
+            .iter()
+            .clone()
+            .map(|f| Self {
+                path: f.path.to_owned(),
+                digest: Some(f.digest.to_owned()),
+                operation: FileOperation::UpdateMetadata,
+                size: f.size,
+                from_path: None,
+                metadata: Some(f.metadata.to_owned()),
+                kind: f.kind.to_owned(),
             })
             .collect();
 
-        [new_files, removed_files, changed_files].concat()
+        [
+            new_files,
+            moved_files,
+            removed_files,
+            changed_files,
+            metadata_changed_files,
+        ]
+        .concat()
     }
 }
 
@@ -112,9 +166,12 @@ impl From<RSFile> for FileUploadRequest {
     fn from(rs_file: RSFile) -> Self {
         Self {
             path: rs_file.path,
-            sha_256_digest: Some(rs_file.sha_256_digest),
+            digest: Some(rs_file.digest),
             operation: FileOperation::Add,
             size: rs_file.size,
+            from_path: None,
+            metadata: Some(rs_file.metadata),
+            kind: rs_file.kind,
         }
     }
 }
@@ -129,13 +186,29 @@ pub enum ApiRequestWrapper<T: Serialize> {
 #[derive(Deserialize, Serialize)]
 pub struct DeclareBackupRequest<'a> {
     pub files: Vec<FileUploadRequest>,
+    #[serde(with = "crate::util::encoded_path")]
     pub root: PathBuf,
     pub name: &'a str,
+    /// Algorithm of every digest in `files`, kept by the backup from now on.
+    pub hash_algorithm: HashAlgorithm,
+    pub config: SharedBackupConfig,
 }
 
 impl<'a> DeclareBackupRequest<'a> {
-    pub fn new(name: &'a str, root: PathBuf, files: Vec<FileUploadRequest>) -> Self {
-        Self { name, root, files }
+    pub fn new(
+        name: &'a str,
+        root: PathBuf,
+        files: Vec<FileUploadRequest>,
+        hash_algorithm: HashAlgorithm,
+        config: SharedBackupConfig,
+    ) -> Self {
+        Self {
+            name,
+            root,
+            files,
+            hash_algorithm,
+            config,
+        }
     }
 }
 
@@ -151,6 +224,9 @@ pub struct DownloadResponse {
     pub download_token: String,
     pub update: Update,
     pub total_bytes: usize,
+    /// Settings the backup was tracked with, only sent when cloning.
+    #[serde(default)]
+    pub config: Option<SharedBackupConfig>,
 }
 
 impl CloneRequest {
@@ -171,6 +247,7 @@ pub struct UploadResponse {
 pub struct PushRequest {
     pub backup_id: String,
     pub files: Vec<FileUploadRequest>,
+    pub hash_algorithm: HashAlgorithm,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -186,14 +263,29 @@ pub struct Backup {
     pub id: String,
     pub name: String,
     pub entrypoint: String,
+    /// Backups created before the algorithm could be chosen are all SHA-256.
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct File {
     pub id: String,
     pub path: String,
-    pub sha256_checksum: String,
+    /// Digest of the content, which keeps its SHA-256 name on the wire for compatibility.
+    #[serde(rename = "sha256_checksum")]
+    pub digest: String,
+    /// Algorithm of `digest`.
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
     pub last_update: FileUpdate,
+    #[serde(default)]
+    pub metadata: Option<FileMetadata>,
+    #[serde(default)]
+    pub kind: FileKind,
+    /// Previous path of the file, only set for `FileOperation::Move`.
+    #[serde(default)]
+    pub from_path: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -1,17 +1,25 @@
-use std::{
-    io::{Read, Write},
-    path::{Path, PathBuf},
-};
+use std::path::{Path, PathBuf};
 
-use crate::model::{DomainError, RedstoneError};
+use crate::{
+    model::{DomainError, RedstoneError},
+    util::write_file_atomically,
+};
 
 use super::{
     api::{Backup, Update},
-    fs_tree::FSTree,
-    Result,
+    digest::HashAlgorithm,
+    file_metadata::MetadataOptions,
+    fs_tree::{ExclusionRules, FSTree, ScanOptions, SymlinkPolicy},
+    index_migrations, Result,
 };
 use serde::{Deserialize, Serialize};
 
+/// Written at the start of every index, followed by its format version.
+const INDEX_MAGIC: &[u8; 4] = b"RSIX";
+
+/// Bumped whenever the layout of `IndexFile` changes, along with a new migration.
+pub const INDEX_FORMAT_VERSION: u32 = 1;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     server_url: String,
@@ -44,39 +52,127 @@ impl IndexFile {
         }
     }
 
+    /// Reads the index, upgrading it when it was written by an older client.
+    ///
+    /// The file itself is left as it is, as read-only commands read it too: the upgraded
+    /// index is written by the next command which saves it.
     pub fn from_file(path: &Path) -> Result<Self> {
-        let metadata = std::fs::metadata(path)?;
-        let mut buffer = vec![0_u8; metadata.len() as usize];
-        let mut file = std::fs::File::open(path)?;
-        file.read_exact(&mut buffer)?;
+        let buffer = std::fs::read(path)?;
         if buffer.is_empty() {
             return Err(RedstoneError::DomainError(DomainError::BackupDoesntExist(
-                path.to_str().unwrap().into(),
+                path.to_string_lossy().into(),
             )));
         }
-        Ok(bincode::deserialize(&buffer)?)
+        let (version, payload) = match buffer.strip_prefix(INDEX_MAGIC) {
+            Some(content) if content.len() >= 4 => {
+                let (version, payload) = content.split_at(4);
+                (u32::from_le_bytes(version.try_into().unwrap()), payload)
+            }
+            Some(_) => {
+                return Err(RedstoneError::SerdeError(format!(
+                    "Index file \"{}\" is truncated",
+                    path.to_string_lossy()
+                )))
+            }
+            // indexes written before the header was introduced
+            None => (0, buffer.as_slice()),
+        };
+        index_migrations::migrate(version, payload)
     }
 
     pub fn save(&self, path: &Path) -> Result<()> {
-        let mut file = std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path)?;
+        write_file_atomically(path, &self.to_bytes()?)
+    }
 
-        Ok(file.write_all(&bincode::serialize(self)?)?)
+    /// Serializes the index along with its header.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = INDEX_MAGIC.to_vec();
+        bytes.extend(INDEX_FORMAT_VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(self)?);
+        Ok(bytes)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct BackupConfig {
     pub sync_every: Option<String>,
     pub watch: bool,
+    pub hashing_threads: Option<usize>,
+    pub preserve_ownership: bool,
+    pub preserve_xattrs: bool,
+    pub symlinks: SymlinkPolicy,
+    pub hash_algorithm: HashAlgorithm,
+    pub exclusions: ExclusionRules,
+    pub git_ignore: bool,
+    pub include: Vec<String>,
+    pub strict: bool,
+}
+
+/// Settings which decide what a backup holds, kept by the server so every clone
+/// scans the files the same way as the machine which tracked it.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SharedBackupConfig {
+    pub preserve_ownership: bool,
+    pub preserve_xattrs: bool,
+    pub symlinks: SymlinkPolicy,
+    pub exclusions: ExclusionRules,
+    pub git_ignore: bool,
+    pub include: Vec<String>,
+    pub strict: bool,
 }
 
 impl BackupConfig {
     pub fn new(sync_every: Option<String>, watch: bool) -> Self {
-        Self { sync_every, watch }
+        Self {
+            sync_every,
+            watch,
+            ..Default::default()
+        }
+    }
+
+    /// Config of a cloned backup, which only keeps the settings the server shares.
+    pub fn from_shared(shared: SharedBackupConfig, hash_algorithm: HashAlgorithm) -> Self {
+        Self {
+            preserve_ownership: shared.preserve_ownership,
+            preserve_xattrs: shared.preserve_xattrs,
+            symlinks: shared.symlinks,
+            hash_algorithm,
+            exclusions: shared.exclusions,
+            git_ignore: shared.git_ignore,
+            include: shared.include,
+            strict: shared.strict,
+            ..Default::default()
+        }
+    }
+
+    pub fn shared(&self) -> SharedBackupConfig {
+        SharedBackupConfig {
+            preserve_ownership: self.preserve_ownership,
+            preserve_xattrs: self.preserve_xattrs,
+            symlinks: self.symlinks,
+            exclusions: self.exclusions.clone(),
+            git_ignore: self.git_ignore,
+            include: self.include.clone(),
+            strict: self.strict,
+        }
+    }
+
+    pub fn scan_options(&self) -> ScanOptions {
+        let mut options = ScanOptions::default();
+        if let Some(hashing_threads) = self.hashing_threads {
+            options.hashing_threads = hashing_threads;
+        }
+        options.metadata = MetadataOptions {
+            preserve_ownership: self.preserve_ownership,
+            preserve_xattrs: self.preserve_xattrs,
+        };
+        options.symlinks = self.symlinks;
+        options.hash_algorithm = self.hash_algorithm;
+        options.exclusions = self.exclusions.clone();
+        options.git_ignore = self.git_ignore;
+        options.include = self.include.clone();
+        options.strict = self.strict;
+        options
     }
 }
 
@@ -86,3 +182,79 @@ pub fn get_index_file_for_path(path: &Path) -> PathBuf {
     path.push("index");
     path
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        model::{
+            fs_tree::{FSTree, ScanOptions},
+            DomainError, RedstoneError,
+        },
+        testing::TempTree,
+        util::generate_digest_from_bytes,
+    };
+
+    use super::{get_index_file_for_path, IndexFile, INDEX_FORMAT_VERSION, INDEX_MAGIC};
+
+    #[test]
+    fn upgrades_indexes_without_header() {
+        let tree = TempTree::new("index-v0");
+        tree.create_dir(".rs");
+        tree.write("file.txt", "content");
+        let index_file_path = get_index_file_for_path(&tree.root);
+        let digest = generate_digest_from_bytes(b"content", Default::default());
+        let update = ("update-id", "hash", "message");
+        // bincode lays structs out as their fields, in order
+        let legacy_index = (
+            (Some("0 * * * * *".to_owned()), true),
+            ("backup-id", "name", "/backup"),
+            (
+                vec![("file.txt", digest.as_str(), 7_u64)],
+                tree.root.clone(),
+            ),
+            update,
+            update,
+        );
+        std::fs::write(&index_file_path, bincode::serialize(&legacy_index).unwrap()).unwrap();
+
+        let index_file = IndexFile::from_file(&index_file_path).unwrap();
+        let content_after_reading = std::fs::read(&index_file_path).unwrap();
+        index_file.save(&index_file_path).unwrap();
+        let content = std::fs::read(&index_file_path).unwrap();
+        let options = ScanOptions {
+            global_ignore_file: None,
+            ..Default::default()
+        };
+        let fs_tree = FSTree::build(tree.root.clone(), &options, None).unwrap();
+
+        assert_eq!(index_file.backup.id, "backup-id");
+        assert_eq!(index_file.config.sync_every.as_deref(), Some("0 * * * * *"));
+        assert_eq!(index_file.last_fs_tree.files[0].path, "file.txt");
+        assert_eq!(index_file.last_fs_tree.files[0].size, 7);
+        assert_eq!(index_file.latest_update.hash, "hash");
+        assert!(!content_after_reading.starts_with(INDEX_MAGIC));
+        assert!(content.starts_with(INDEX_MAGIC));
+        // metadata wasn't recorded back then, so the next push sends it along
+        let diff = fs_tree.diff(&index_file.last_fs_tree).unwrap();
+        assert!(diff.new_files.is_empty());
+        assert!(diff.changed_files.is_empty());
+        assert_eq!(diff.metadata_changed_files.len(), 1);
+    }
+
+    #[test]
+    fn refuses_indexes_from_newer_clients() {
+        let tree = TempTree::new("index-new");
+        let path = tree.path("index");
+        let mut content = INDEX_MAGIC.to_vec();
+        content.extend((INDEX_FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, content).unwrap();
+
+        let result = IndexFile::from_file(&path);
+
+        assert!(matches!(
+            result,
+            Err(RedstoneError::DomainError(DomainError::IndexFromNewerClient(version)))
+                if version == INDEX_FORMAT_VERSION + 1
+        ));
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{DomainError, RedstoneError, Result};
+use fs2::FileExt;
+
+/// Advisory lock on a backup, held for as long as an operation changes it.
+///
+/// It's released when dropped, along with the file, which also happens when the
+/// holding process dies.
+pub struct BackupLock {
+    _file: File,
+}
+
+impl BackupLock {
+    /// Locks the backup at `root` for `operation`, failing with `BackupBusy` when
+    /// another operation already holds it.
+    pub fn try_acquire(root: &Path, operation: &str) -> Result<Self> {
+        let mut file = open_lock_file(root)?;
+        if let Err(err) = file.try_lock_exclusive() {
+            if err.kind() != fs2::lock_contended_error().kind() {
+                return Err(err.into());
+            }
+            let holder = read_holder(&mut file);
+            return Err(RedstoneError::DomainError(DomainError::BackupBusy(holder)));
+        }
+        Self::hold(file, operation)
+    }
+
+    /// Locks the backup at `root` for `operation`, blocking until the operation
+    /// holding it is done.
+    pub fn acquire(root: &Path, operation: &str) -> Result<Self> {
+        let file = open_lock_file(root)?;
+        file.lock_exclusive()?;
+        Self::hold(file, operation)
+    }
+
+    fn hold(mut file: File, operation: &str) -> Result<Self> {
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(operation.as_bytes())?;
+        Ok(Self { _file: file })
+    }
+}
+
+fn open_lock_file(root: &Path) -> Result<File> {
+    let path = get_lock_file_for_path(root);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?)
+}
+
+/// Operation holding the lock, which may still be writing itself in, so it's not
+/// always known.
+fn read_holder(file: &mut File) -> Option<String> {
+    let mut operation = String::new();
+    file.read_to_string(&mut operation).ok()?;
+    Some(operation).filter(|operation| !operation.is_empty())
+}
+
+pub fn get_lock_file_for_path(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    path.push(".rs");
+    path.push("lock");
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{DomainError, RedstoneError};
+    use crate::testing::TempTree;
+
+    use super::BackupLock;
+
+    #[test]
+    fn refuses_a_second_holder_until_released() {
+        let tree = TempTree::new("lock");
+
+        let lock = BackupLock::try_acquire(&tree.root, "push").unwrap();
+        let busy = BackupLock::try_acquire(&tree.root, "pull");
+        drop(lock);
+        let released = BackupLock::try_acquire(&tree.root, "pull");
+
+        assert!(matches!(
+            busy,
+            Err(RedstoneError::DomainError(DomainError::BackupBusy(Some(holder))))
+                if holder == "push"
+        ));
+        assert!(released.is_ok());
+    }
+
+    #[test]
+    fn waits_for_the_holder_to_release_it() {
+        let tree = TempTree::new("lock-wait");
+        let lock = BackupLock::try_acquire(&tree.root, "push").unwrap();
+
+        let root = tree.root.clone();
+        let waiting = std::thread::spawn(move || BackupLock::acquire(&root, "pull").map(drop));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        let was_waiting = !waiting.is_finished();
+        drop(lock);
+
+        assert!(was_waiting);
+        assert!(waiting.join().unwrap().is_ok());
+    }
+}
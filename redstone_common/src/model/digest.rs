@@ -0,0 +1,66 @@
+use std::str::FromStr;
+
+use data_encoding::HEXLOWER;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Algorithm used to hash the content of a backup's files.
+///
+/// Every backup keeps the algorithm it was tracked with, digests made by
+/// different algorithms are never compared against each other.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    /// Used by every backup created before the algorithm could be chosen.
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn hasher(&self) -> ContentHasher {
+        match self {
+            Self::Sha256 => ContentHasher::Sha256(Sha256::new()),
+            Self::Blake3 => ContentHasher::Blake3(Box::default()),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(algorithm: &str) -> std::result::Result<Self, Self::Err> {
+        match algorithm {
+            "sha256" => Ok(Self::Sha256),
+            "blake3" => Ok(Self::Blake3),
+            _ => Err(format!(
+                "Invalid hash algorithm \"{algorithm}\", expected one of: sha256, blake3"
+            )),
+        }
+    }
+}
+
+/// Incremental hasher for any of the supported algorithms.
+pub enum ContentHasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl ContentHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Consumes the hasher, returning the lowercase hex digest.
+    pub fn finalize(self) -> String {
+        match self {
+            Self::Sha256(hasher) => HEXLOWER.encode(hasher.finalize().as_ref()),
+            Self::Blake3(hasher) => HEXLOWER.encode(hasher.finalize().as_bytes()),
+        }
+    }
+}
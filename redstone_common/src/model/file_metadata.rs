@@ -0,0 +1,103 @@
+use std::{
+    collections::BTreeMap,
+    fs::Metadata,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::Result;
+
+/// POSIX metadata restored alongside the file's content.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct FileMetadata {
+    pub mode: Option<u32>,
+    pub mtime: i64,
+    pub mtime_nsec: u32,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetadataOptions {
+    pub preserve_ownership: bool,
+    pub preserve_xattrs: bool,
+}
+
+impl FileMetadata {
+    pub fn read(path: &Path, metadata: &Metadata, options: MetadataOptions) -> Result<Self> {
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut file_metadata = Self {
+            mtime: mtime.as_secs() as i64,
+            mtime_nsec: mtime.subsec_nanos(),
+            ..Default::default()
+        };
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            file_metadata.mode = Some(metadata.mode() & 0o7777);
+            if options.preserve_ownership {
+                file_metadata.uid = Some(metadata.uid());
+                file_metadata.gid = Some(metadata.gid());
+            }
+            if options.preserve_xattrs {
+                file_metadata.xattrs = read_xattrs(path)?;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = (path, options);
+
+        Ok(file_metadata)
+    }
+
+    /// Applies the metadata to an already written file.
+    ///
+    /// Ownership is applied on a best effort basis, since only privileged users can give
+    /// files away. The mode is set last, as `chown` clears setuid bits and a restrictive
+    /// mode could prevent the file from being opened to set its mtime.
+    pub fn apply(&self, path: &Path) -> Result<()> {
+        #[cfg(unix)]
+        {
+            for (name, value) in self.xattrs.iter() {
+                xattr::set(path, name, value)?;
+            }
+            if self.uid.is_some() || self.gid.is_some() {
+                match std::os::unix::fs::chown(path, self.uid, self.gid) {
+                    Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {}
+                    result => result?,
+                }
+            }
+        }
+
+        let mtime = UNIX_EPOCH + Duration::new(self.mtime.max(0) as u64, self.mtime_nsec);
+        std::fs::File::open(path)?.set_modified(mtime)?;
+
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn read_xattrs(path: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut xattrs = BTreeMap::new();
+    if !xattr::SUPPORTED_PLATFORM {
+        return Ok(xattrs);
+    }
+    for name in xattr::list(path)? {
+        let (Some(key), Some(value)) = (name.to_str(), xattr::get(path, &name)?) else {
+            continue;
+        };
+        xattrs.insert(key.to_owned(), value);
+    }
+    Ok(xattrs)
+}
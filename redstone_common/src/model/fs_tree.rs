@@ -1,60 +1,223 @@
 use colored::Colorize;
-use ignore::WalkBuilder;
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::{HashMap, HashSet},
     ffi::OsStr,
-    fmt::Debug,
+    fmt::{Debug, Display},
+    io::Read,
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use crate::util::generate_sha256_digest;
+use crate::{
+    config::get_global_ignore_file,
+    util::{
+        bytes_to_human_readable, encode_path, generate_digest, generate_digest_from_bytes,
+        normalize_remote_path,
+    },
+};
 
 use super::{
+    digest::HashAlgorithm,
+    file_metadata::{FileMetadata, MetadataOptions},
+    ignore_rules::IgnoreRules,
+    include_rules::{IncludeRules, Inclusion},
     ipc::{FileAction, FileActionProgress},
+    stat_cache::{get_stat_cache_file_for_path, FileStat, StatCache},
     ArgumentError, RedstoneError, Result,
 };
 
-type Sha256Digest = String;
+const CACHEDIR_TAG_SIGNATURE: &[u8; 43] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// Amount of worker threads used to hash files (0 lets the pool decide).
+    pub hashing_threads: usize,
+    /// Rehash every file instead of reusing digests from the stat cache.
+    pub paranoid: bool,
+    pub metadata: MetadataOptions,
+    pub symlinks: SymlinkPolicy,
+    pub hash_algorithm: HashAlgorithm,
+    /// User-wide ignore file, applied on top of the backup's `.rsignore` files.
+    pub global_ignore_file: Option<PathBuf>,
+    /// Also honor `.gitignore`, `.ignore` and git's excludes, below `.rsignore` files.
+    pub git_ignore: bool,
+    /// Allowlist patterns, added to the ones in the root's `.rsinclude` file.
+    pub include: Vec<String>,
+    /// Fail on the first entry which can't be read, instead of skipping it.
+    pub strict: bool,
+    pub exclusions: ExclusionRules,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        let hashing_threads = std::thread::available_parallelism()
+            .map(|threads| threads.get())
+            .unwrap_or(1);
+        Self {
+            hashing_threads,
+            paranoid: false,
+            metadata: MetadataOptions::default(),
+            symlinks: SymlinkPolicy::default(),
+            hash_algorithm: HashAlgorithm::default(),
+            global_ignore_file: get_global_ignore_file().ok(),
+            git_ignore: false,
+            include: Vec::new(),
+            strict: false,
+            exclusions: ExclusionRules::default(),
+        }
+    }
+}
+
+/// Rules which leave entries out of the backup based on their attributes, not their paths.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ExclusionRules {
+    /// Files bigger than this amount of bytes are excluded.
+    pub max_file_size: Option<u64>,
+    /// Files which weren't modified within this amount of days are excluded.
+    pub max_age_days: Option<u64>,
+    /// Exclude folders tagged by a `CACHEDIR.TAG` file.
+    pub exclude_caches: bool,
+    /// Don't descend into folders mounted from other file systems than the root's.
+    pub one_file_system: bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ExclusionReason {
+    TooLarge(u64),
+    TooOld,
+    CacheDirectory,
+    OtherFileSystem,
+}
+
+impl Display for ExclusionReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            Self::TooLarge(size) => {
+                format!("too large, {}", bytes_to_human_readable(*size as usize))
+            }
+            Self::TooOld => String::from("not modified recently"),
+            Self::CacheDirectory => String::from("cache folder"),
+            Self::OtherFileSystem => String::from("other file system"),
+        };
+        write!(f, "{reason}")
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ExcludedEntry {
+    pub path: String,
+    pub reason: ExclusionReason,
+}
+
+/// Entry which couldn't be read, such as a file without read permissions
+/// or one which was deleted while being scanned.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SkippedEntry {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Store the link itself, restoring it pointing to the same target.
+    #[default]
+    Store,
+    /// Back up whatever the link points to, as if it was a regular file or folder.
+    Follow,
+    /// Leave links out of the backup.
+    Skip,
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> std::result::Result<Self, Self::Err> {
+        match policy {
+            "store" => Ok(Self::Store),
+            "follow" => Ok(Self::Follow),
+            "skip" => Ok(Self::Skip),
+            _ => Err(format!(
+                "Invalid symlink policy \"{policy}\", expected one of: store, follow, skip"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    #[default]
+    File,
+    Symlink {
+        target: String,
+    },
+    /// Only empty folders are stored, the others are implied by the files in them.
+    Directory,
+    /// A file sharing its content with `target`, the first of its links in the backup,
+    /// so the content is only sent once.
+    HardLink {
+        target: String,
+    },
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct RSFile {
     pub path: String,
-    pub sha_256_digest: String,
+    pub digest: String,
     pub size: u64,
+    pub metadata: FileMetadata,
+    pub kind: FileKind,
 }
 
 impl RSFile {
-    pub fn new(path: String, sha_256_digest: String, size: u64) -> Self {
+    pub fn new(path: String, digest: String, size: u64) -> Self {
         Self {
             path,
-            sha_256_digest,
+            digest,
             size,
+            metadata: FileMetadata::default(),
+            kind: FileKind::File,
         }
     }
 }
 
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MovedFile {
+    pub from: RSFile,
+    pub to: RSFile,
+}
+
 #[derive(Debug, Default)]
 pub struct FSTreeDiff {
     pub new_files: Vec<RSFile>,
     pub changed_files: Vec<RSFile>,
+    pub metadata_changed_files: Vec<RSFile>,
+    pub moved_files: Vec<MovedFile>,
     pub removed_files: Vec<RSFile>,
 }
 
 impl FSTreeDiff {
     pub fn has_changes(&self) -> bool {
-        (self.new_files.len() + self.changed_files.len() + self.removed_files.len()) > 0
+        (self.new_files.len()
+            + self.changed_files.len()
+            + self.metadata_changed_files.len()
+            + self.moved_files.len()
+            + self.removed_files.len())
+            > 0
     }
 
     pub fn total_size(&self) -> u64 {
-        [
-            self.new_files.clone(),
-            self.changed_files.clone(),
-            self.removed_files.clone(),
-        ]
-        .concat()
-        .iter()
-        .map(|file| file.size)
-        .sum()
+        self.new_files
+            .iter()
+            .chain(self.changed_files.iter())
+            .chain(self.removed_files.iter())
+            .filter(|file| !matches!(file.kind, FileKind::HardLink { .. }))
+            .map(|file| file.size)
+            .sum()
     }
 
     pub fn get_changes_message(&self) -> String {
@@ -90,6 +253,21 @@ impl FSTreeDiff {
                 message += &format!("{}\n", path.purple());
             }
         }
+        if !self.metadata_changed_files.is_empty() {
+            message += "\nMetadata changed:\n";
+            for file in self.metadata_changed_files.iter() {
+                message += &format!("{}\n", file.path.yellow());
+            }
+        }
+
+        if !self.moved_files.is_empty() {
+            message += "\nMoved:\n";
+            for moved_file in self.moved_files.iter() {
+                let paths = format!("{} -> {}", moved_file.from.path, moved_file.to.path);
+                message += &format!("{}\n", paths.blue());
+            }
+        }
+
         let removed_files = self
             .removed_files
             .iter()
@@ -110,183 +288,705 @@ impl FSTreeDiff {
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct FSTree {
     pub files: Vec<RSFile>,
+    #[serde(with = "crate::util::encoded_path")]
     pub root: PathBuf,
+    /// Algorithm which produced the digests of `files`.
+    pub hash_algorithm: HashAlgorithm,
+    /// Entries left out by the exclusion rules during the scan which built this tree.
+    #[serde(skip)]
+    pub excluded: Vec<ExcludedEntry>,
+    /// Entries which couldn't be read during the scan which built this tree.
+    #[serde(skip)]
+    pub skipped: Vec<SkippedEntry>,
 }
 
 impl FSTree {
     pub fn build(
         root: PathBuf,
-        progress_handler_fn: Option<&dyn Fn(FileActionProgress)>,
+        options: &ScanOptions,
+        progress_handler_fn: Option<&(dyn Fn(FileActionProgress) + Sync)>,
     ) -> Result<FSTree> {
-        let mut fs_tree = Self::build_base(root)?;
-        let files = read_dir(&fs_tree.root, 0, &mut Vec::new())?;
-        fs_tree.files = build_rs_files(&fs_tree.root, files, progress_handler_fn)?;
+        let stat_cache_path = get_stat_cache_file_for_path(&root);
+        let stat_cache = StatCache::from_file(&stat_cache_path, options.hash_algorithm);
+        let (fs_tree, stat_cache) =
+            Self::build_with_cache(root, options, &stat_cache, progress_handler_fn)?;
+
+        // only backups (which already have a `.rs` folder) persist the cache
+        if stat_cache_path.parent().is_some_and(Path::is_dir) {
+            stat_cache.save(&stat_cache_path)?;
+        }
         Ok(fs_tree)
     }
 
+    /// Scans the tree like `build`, without updating the stat cache, for commands
+    /// which only look at the backup.
+    pub fn build_read_only(root: PathBuf, options: &ScanOptions) -> Result<FSTree> {
+        let stat_cache =
+            StatCache::from_file(&get_stat_cache_file_for_path(&root), options.hash_algorithm);
+        Self::build_with_cache(root, options, &stat_cache, None).map(|(fs_tree, _)| fs_tree)
+    }
+
+    fn build_with_cache(
+        root: PathBuf,
+        options: &ScanOptions,
+        stat_cache: &StatCache,
+        progress_handler_fn: Option<&(dyn Fn(FileActionProgress) + Sync)>,
+    ) -> Result<(FSTree, StatCache)> {
+        let scan_started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_default();
+        let mut fs_tree = Self::build_base(root, options.hash_algorithm)?;
+        let mut scan_context = ScanContext::new(&fs_tree.root, options, scan_started_at)?;
+        let root_inclusion = match scan_context.include_rules {
+            Some(_) => Inclusion::Descend,
+            None => Inclusion::Included,
+        };
+        let files = read_dir(&fs_tree.root, 0, root_inclusion, &mut scan_context)?;
+        let scanned_files = build_rs_files(
+            &fs_tree.root,
+            &files,
+            options,
+            stat_cache,
+            progress_handler_fn,
+        )?;
+
+        let mut new_stat_cache = StatCache::new(options.hash_algorithm);
+        let mut indexes_by_path: HashMap<String, usize> = HashMap::new();
+        for (entry, scanned_file) in files.iter().zip(scanned_files) {
+            let (mut file, stat) = match scanned_file {
+                Ok(scanned_file) => scanned_file,
+                Err(err) => {
+                    scan_context.skip(entry.path(), err)?;
+                    continue;
+                }
+            };
+            // files modified during the scan could change again within the same
+            // timestamp, so they're only cached once they've settled
+            if let Some(stat) = stat.filter(|stat| stat.mtime < scan_started_at) {
+                new_stat_cache.insert(file.path.clone(), stat, file.digest.clone());
+            }
+            if let FileKind::HardLink { target } = &file.kind {
+                // the target always comes first, as it's the first link found by the walk
+                let Some(target_file) = indexes_by_path.get(target).map(|idx| &fs_tree.files[*idx])
+                else {
+                    let error = RedstoneError::BaseError(format!("{target} couldn't be read"));
+                    scan_context.skip(entry.path(), error)?;
+                    continue;
+                };
+                file.digest = target_file.digest.clone();
+                file.size = target_file.size;
+                file.metadata = target_file.metadata.clone();
+            }
+            indexes_by_path.insert(file.path.clone(), fs_tree.files.len());
+            fs_tree.files.push(file);
+        }
+        fs_tree.excluded = scan_context.excluded;
+        fs_tree.skipped = scan_context.skipped;
+        Ok((fs_tree, new_stat_cache))
+    }
+
+    /// Size of the content to transfer, which hard links don't add to.
     pub fn total_size(&self) -> u64 {
-        self.files.iter().map(|file| file.size).sum()
+        self.files
+            .iter()
+            .filter(|file| !matches!(file.kind, FileKind::HardLink { .. }))
+            .map(|file| file.size)
+            .sum()
     }
 
-    pub fn get_conflicting_files(&self, file_paths: Vec<String>) -> Vec<RSFile> {
-        let conflicting_files = self
+    /// Symlinked folders which the files of this tree were found through, relative to its root.
+    pub fn followed_links(&self) -> HashSet<PathBuf> {
+        let mut checked_folders = HashSet::new();
+        let mut followed_links = HashSet::new();
+        for path in self
             .files
             .iter()
-            .filter(|file| file_paths.contains(&file.path.to_string()))
-            .cloned()
+            .filter_map(|file| normalize_remote_path(&file.path).ok())
+        {
+            for folder in path.ancestors().skip(1) {
+                if folder.as_os_str().is_empty() || !checked_folders.insert(folder.to_path_buf()) {
+                    continue;
+                }
+                let is_symlink = std::fs::symlink_metadata(self.root.join(folder))
+                    .is_ok_and(|metadata| metadata.is_symlink());
+                if is_symlink {
+                    followed_links.insert(folder.to_path_buf());
+                }
+            }
+        }
+        followed_links
+    }
+
+    pub fn get_excluded_message(&self) -> String {
+        if self.excluded.is_empty() {
+            return String::new();
+        }
+        let mut message = String::from("\nExcluded:\n");
+        for entry in self.excluded.iter() {
+            message += &format!(
+                "{}\n",
+                format!("{} ({})", entry.path, entry.reason).dimmed()
+            );
+        }
+        message
+    }
+
+    pub fn get_skipped_message(&self) -> String {
+        if self.skipped.is_empty() {
+            return String::new();
+        }
+        let mut message = String::from("\nSkipped, as they couldn't be read:\n");
+        for entry in self.skipped.iter() {
+            message += &format!(
+                "{}\n",
+                format!("{}: {}", entry.path, entry.reason).bright_red()
+            );
+        }
+        message
+    }
+
+    /// Carries the entries of skipped and excluded paths over from an older tree, so
+    /// files which couldn't be read this time, or which an exclusion rule leaves out
+    /// since they were tracked, aren't mistaken for removed ones.
+    pub fn keep_unscanned_files(&mut self, old_fs_tree: &Self) {
+        let unscanned_paths: HashSet<&Path> = self
+            .skipped
+            .iter()
+            .map(|entry| Path::new(&entry.path))
+            .chain(self.excluded.iter().map(|entry| Path::new(&entry.path)))
             .collect();
-        conflicting_files
+        if unscanned_paths.is_empty() {
+            return;
+        }
+        let kept_files = old_fs_tree.files.iter().filter(|file| {
+            Path::new(&file.path)
+                .ancestors()
+                .any(|path| unscanned_paths.contains(path))
+        });
+        self.files.extend(kept_files.cloned());
+    }
+
+    pub fn get_conflicting_files(&self, file_paths: Vec<String>) -> Vec<RSFile> {
+        let file_paths: HashSet<String> = file_paths.into_iter().collect();
+        self.files
+            .iter()
+            .filter(|file| file_paths.contains(&file.path))
+            .cloned()
+            .collect()
     }
 
+    /// Compares this tree against an older one.
+    ///
+    /// Lookups are indexed by path, so diffing is linear on the size of both trees.
+    /// Every category keeps the order in which files appear in their tree.
     pub fn diff(&self, old_fs_tree: &Self) -> Result<FSTreeDiff> {
+        if self.hash_algorithm != old_fs_tree.hash_algorithm {
+            return Err(RedstoneError::BaseError(format!(
+                "Can't compare files hashed with {:?} against files hashed with {:?}.",
+                self.hash_algorithm, old_fs_tree.hash_algorithm
+            )));
+        }
+        let old_files_by_path: HashMap<&str, &RSFile> = old_fs_tree
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file))
+            .collect();
         let mut new_files = vec![];
         let mut changed_files = vec![];
+        let mut metadata_changed_files = vec![];
 
-        for file in self.files.clone() {
-            let old_file = old_fs_tree
-                .files
-                .iter()
-                .find(|old_file| old_file.path == file.path);
-
-            if old_file.is_none() {
-                new_files.push(file);
-                continue;
-            }
-            let old_file = old_file.unwrap();
-            if file.sha_256_digest != old_file.sha_256_digest {
-                changed_files.push(file);
-                continue;
+        for file in self.files.iter() {
+            match old_files_by_path.get(file.path.as_str()) {
+                None => new_files.push(file.clone()),
+                Some(old_file) if old_file.digest != file.digest || old_file.kind != file.kind => {
+                    changed_files.push(file.clone())
+                }
+                Some(old_file) if old_file.metadata != file.metadata => {
+                    metadata_changed_files.push(file.clone())
+                }
+                Some(_) => {}
             }
         }
 
+        let current_paths: HashSet<&str> =
+            self.files.iter().map(|file| file.path.as_str()).collect();
         let removed_files = old_fs_tree
             .files
             .iter()
-            .filter(|old_file| !self.files.iter().any(|file| old_file.path == file.path))
+            .filter(|old_file| !current_paths.contains(old_file.path.as_str()))
             .cloned()
             .collect();
 
+        let (new_files, moved_files, removed_files) = pair_moved_files(new_files, removed_files);
+
         Ok(FSTreeDiff {
             new_files,
             changed_files,
+            metadata_changed_files,
+            moved_files,
             removed_files,
         })
     }
 
-    fn build_base(root: PathBuf) -> Result<FSTree> {
+    fn build_base(root: PathBuf, hash_algorithm: HashAlgorithm) -> Result<FSTree> {
         let root_is_file = root.is_file();
         let fs_tree = FSTree {
             root,
             files: Vec::new(),
+            hash_algorithm,
+            excluded: Vec::new(),
+            skipped: Vec::new(),
         };
 
-        let root_as_string = &fs_tree.root.to_str().unwrap();
         if root_is_file {
+            let root_as_string = fs_tree.root.to_string_lossy().into_owned();
             return Err(RedstoneError::ArgumentError(
-                ArgumentError::PathCannotBeAFile(String::from(*root_as_string)),
+                ArgumentError::PathCannotBeAFile(root_as_string),
             ));
         }
         Ok(fs_tree)
     }
 }
 
-fn read_dir(dir: &PathBuf, depth: u16, ignores: &mut Vec<String>) -> Result<Vec<PathBuf>> {
-    let mut file_tree_items = Vec::new();
-    {
-        let mut dir = dir.clone();
-        dir.push(".rsignore");
-        if dir.exists() {
-            ignores.push(String::from(dir.to_str().unwrap()));
+/// File built out of a scanned entry, along with the stat data its digest is cached with.
+type ScannedFile = (RSFile, Option<FileStat>);
+
+enum ScannedEntry {
+    File(PathBuf),
+    Symlink(PathBuf),
+    EmptyDirectory(PathBuf),
+    HardLink { path: PathBuf, target: PathBuf },
+}
+
+impl ScannedEntry {
+    fn path(&self) -> &Path {
+        match self {
+            Self::File(path)
+            | Self::Symlink(path)
+            | Self::EmptyDirectory(path)
+            | Self::HardLink { path, .. } => path,
+        }
+    }
+}
+
+struct ScanContext<'a> {
+    root: PathBuf,
+    options: &'a ScanOptions,
+    ignore_rules: IgnoreRules,
+    include_rules: Option<IncludeRules>,
+    /// Canonical paths of the folders being walked, used to detect symlink loops.
+    ancestors: Vec<PathBuf>,
+    root_device: u64,
+    /// Files modified before this timestamp are excluded.
+    min_mtime: Option<i64>,
+    excluded: Vec<ExcludedEntry>,
+    skipped: Vec<SkippedEntry>,
+    /// First file found for each (device, inode) pair with more than one link.
+    hard_links: HashMap<(u64, u64), PathBuf>,
+}
+
+impl<'a> ScanContext<'a> {
+    fn new(root: &Path, options: &'a ScanOptions, scan_started_at: i64) -> Result<Self> {
+        let min_mtime = options
+            .exclusions
+            .max_age_days
+            .map(|days| scan_started_at - days as i64 * 24 * 60 * 60);
+        Ok(Self {
+            root: root.to_path_buf(),
+            options,
+            ignore_rules: IgnoreRules::new(root, options)?,
+            include_rules: IncludeRules::new(root, &options.include)?,
+            ancestors: Vec::new(),
+            root_device: FileStat::from(&std::fs::metadata(root)?).device,
+            min_mtime,
+            excluded: Vec::new(),
+            skipped: Vec::new(),
+            hard_links: HashMap::new(),
+        })
+    }
+
+    fn get_dir_exclusion(&self, dir: &Path) -> Result<Option<ExclusionReason>> {
+        let rules = &self.options.exclusions;
+        if rules.one_file_system
+            && FileStat::from(&std::fs::metadata(dir)?).device != self.root_device
+        {
+            return Ok(Some(ExclusionReason::OtherFileSystem));
+        }
+        if rules.exclude_caches && is_cache_dir(dir) {
+            return Ok(Some(ExclusionReason::CacheDirectory));
         }
-        dir.push("..");
+        Ok(None)
     }
 
-    let mut builder_base = WalkBuilder::new(dir);
-    for previous_ignore in ignores.iter_mut() {
-        builder_base.add_custom_ignore_filename(previous_ignore);
+    fn get_file_exclusion(&self, file: &Path) -> Result<Option<ExclusionReason>> {
+        let max_file_size = self.options.exclusions.max_file_size;
+        if max_file_size.is_none() && self.min_mtime.is_none() {
+            return Ok(None);
+        }
+        let stat = FileStat::from(&std::fs::metadata(file)?);
+        if max_file_size.is_some_and(|max_file_size| stat.size > max_file_size) {
+            return Ok(Some(ExclusionReason::TooLarge(stat.size)));
+        }
+        if self
+            .min_mtime
+            .is_some_and(|min_mtime| stat.mtime < min_mtime)
+        {
+            return Ok(Some(ExclusionReason::TooOld));
+        }
+        Ok(None)
+    }
+
+    /// Finds the file which was first found with the same content, when the file
+    /// is a hard link to it.
+    fn get_hard_link_target(&mut self, file: &Path) -> Result<Option<PathBuf>> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let metadata = std::fs::metadata(file)?;
+            if metadata.nlink() > 1 {
+                let target = self
+                    .hard_links
+                    .entry((metadata.dev(), metadata.ino()))
+                    .or_insert_with(|| file.to_path_buf());
+                return Ok(Some(target.clone()).filter(|target| target != file));
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = file;
+        Ok(None)
+    }
+
+    fn exclude(&mut self, path: &Path, reason: ExclusionReason) {
+        self.excluded.push(ExcludedEntry {
+            path: build_relative_file_path(path, &self.root),
+            reason,
+        });
+    }
+
+    /// Records an entry which couldn't be read, unless the scan is strict.
+    fn skip(&mut self, path: &Path, error: RedstoneError) -> Result<()> {
+        if self.options.strict {
+            return Err(error);
+        }
+        self.skipped.push(SkippedEntry {
+            path: build_relative_file_path(path, &self.root),
+            reason: error.to_string(),
+        });
+        Ok(())
     }
+}
 
-    for entry in builder_base
-        .standard_filters(false)
-        .hidden(false)
-        .max_depth(Some(1))
-        .build()
-    {
-        let entry = entry?;
-        let path = PathBuf::from(entry.path());
-        if path.is_dir() && path != *dir && !is_rs_dir(&path, depth) {
-            file_tree_items.extend(read_dir(&path, depth + 1, ignores)?);
-        } else if path.is_file() {
-            file_tree_items.push(path);
+/// Reads a folder recursively, only looking into the entries which may be included
+/// when the folder itself isn't.
+///
+/// Entries which can't be read are skipped, unless the scan is strict. The folder
+/// itself has to be readable though, as there would be nothing to scan otherwise.
+fn read_dir(
+    dir: &PathBuf,
+    depth: u16,
+    inclusion: Inclusion,
+    context: &mut ScanContext,
+) -> Result<Vec<ScannedEntry>> {
+    let canonical_dir = dir.canonicalize()?;
+    let mut entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    context.ignore_rules.enter_dir(dir)?;
+    context.ancestors.push(canonical_dir);
+
+    let mut file_tree_items = Vec::new();
+    for entry in entries {
+        if let Err(err) = read_entry(&entry, depth, inclusion, context, &mut file_tree_items) {
+            context.skip(&entry.path(), err)?;
         }
     }
+    context.ancestors.pop();
+    context.ignore_rules.leave_dir();
     Ok(file_tree_items)
 }
 
+fn read_entry(
+    entry: &std::fs::DirEntry,
+    depth: u16,
+    inclusion: Inclusion,
+    context: &mut ScanContext,
+    file_tree_items: &mut Vec<ScannedEntry>,
+) -> Result<()> {
+    let path = entry.path();
+    let is_symlink = entry.file_type()?.is_symlink();
+    let is_followed_dir =
+        path.is_dir() && (!is_symlink || context.options.symlinks == SymlinkPolicy::Follow);
+    if context.ignore_rules.is_ignored(&path, is_followed_dir) {
+        return Ok(());
+    }
+    let inclusion = match (inclusion, &context.include_rules) {
+        (Inclusion::Descend, Some(include_rules)) => {
+            include_rules.check(path.strip_prefix(&context.root).unwrap_or(&path))
+        }
+        (inclusion, _) => inclusion,
+    };
+    let is_included = inclusion == Inclusion::Included;
+    if inclusion == Inclusion::Excluded {
+        return Ok(());
+    }
+    if is_symlink {
+        match context.options.symlinks {
+            SymlinkPolicy::Skip => return Ok(()),
+            SymlinkPolicy::Store => {
+                if is_included {
+                    file_tree_items.push(ScannedEntry::Symlink(path));
+                }
+                return Ok(());
+            }
+            SymlinkPolicy::Follow => {}
+        }
+    }
+
+    if path.is_dir() && !is_rs_dir(&path, depth) {
+        if is_symlink && context.ancestors.contains(&path.canonicalize()?) {
+            // following this link would walk the same folders forever
+            return Ok(());
+        }
+        if let Some(reason) = context.get_dir_exclusion(&path)? {
+            context.exclude(&path, reason);
+            return Ok(());
+        }
+        let dir_items = read_dir(&path, depth + 1, inclusion, context)?;
+        if dir_items.is_empty() && is_included {
+            file_tree_items.push(ScannedEntry::EmptyDirectory(path));
+        }
+        file_tree_items.extend(dir_items);
+    } else if path.is_file() && is_included {
+        if let Some(reason) = context.get_file_exclusion(&path)? {
+            context.exclude(&path, reason);
+            return Ok(());
+        }
+        match context.get_hard_link_target(&path)? {
+            Some(target) => file_tree_items.push(ScannedEntry::HardLink { path, target }),
+            None => file_tree_items.push(ScannedEntry::File(path)),
+        }
+    }
+    Ok(())
+}
+
 fn build_rs_files(
     root: &Path,
-    files: Vec<PathBuf>,
-    progress_handler_fn: Option<&dyn Fn(FileActionProgress)>,
-) -> Result<Vec<RSFile>> {
+    files: &[ScannedEntry],
+    options: &ScanOptions,
+    stat_cache: &StatCache,
+    progress_handler_fn: Option<&(dyn Fn(FileActionProgress) + Sync)>,
+) -> Result<Vec<Result<ScannedFile>>> {
     let files_count = files.len() as u64;
-    let mut files_hashed = 0;
-    files
-        .iter()
-        .map(|path| {
-            let file_path = build_relative_file_path(path, root);
-            let size = std::fs::metadata(path)?.len();
-
-            if let Some(handler) = progress_handler_fn {
-                handler(FileActionProgress {
-                    total: files_count,
-                    current_file_name: file_path.to_owned(),
-                    progress: files_hashed,
-                    operation: FileAction::Hash,
-                });
-            }
+    let files_hashed = Mutex::new(0_u64);
+    let pool = get_hashing_pool(options.hashing_threads)?;
 
-            let sha256_digest: Sha256Digest = generate_sha256_digest(path)?;
-            files_hashed += 1;
-            Ok(RSFile::new(file_path, sha256_digest, size))
-        })
-        .collect::<Result<_>>()
+    // `collect` on an indexed parallel iterator keeps the input order,
+    // so the resulting tree doesn't depend on the amount of threads.
+    // Failures are returned per file, so they can be skipped without losing the others.
+    Ok(pool.install(|| {
+        files
+            .par_iter()
+            .map(|entry| {
+                let scanned_file = match entry {
+                    ScannedEntry::File(path) => build_rs_file(root, path, options, stat_cache),
+                    ScannedEntry::Symlink(path) => {
+                        build_rs_symlink(root, path, options.hash_algorithm)
+                            .map(|rs_file| (rs_file, None))
+                    }
+                    ScannedEntry::EmptyDirectory(path) => {
+                        Ok((build_rs_directory(root, path, options.hash_algorithm), None))
+                    }
+                    ScannedEntry::HardLink { path, target } => {
+                        Ok((build_rs_hard_link(root, path, target), None))
+                    }
+                };
+
+                if let Some(handler) = progress_handler_fn {
+                    // the lock is held while emitting so progress is always reported in order
+                    let mut files_hashed = files_hashed.lock().unwrap();
+                    *files_hashed += 1;
+                    handler(FileActionProgress {
+                        total: files_count,
+                        current_file_name: build_relative_file_path(entry.path(), root),
+                        progress: *files_hashed,
+                        operation: FileAction::Hash,
+                    });
+                }
+
+                scanned_file
+            })
+            .collect()
+    }))
 }
 
-fn is_rs_dir(path: &Path, depth: u16) -> bool {
-    return depth == 0 && path.file_name() == Some(OsStr::new(".rs"));
+/// Pool hashing files with the given amount of threads, which is only built by the
+/// first scan using it, as backups keep scanning with the same settings.
+fn get_hashing_pool(threads: usize) -> Result<Arc<ThreadPool>> {
+    static POOLS: OnceLock<Mutex<HashMap<usize, Arc<ThreadPool>>>> = OnceLock::new();
+    let mut pools = POOLS.get_or_init(Default::default).lock().unwrap();
+    if let Some(pool) = pools.get(&threads) {
+        return Ok(pool.clone());
+    }
+    let pool = Arc::new(ThreadPoolBuilder::new().num_threads(threads).build()?);
+    pools.insert(threads, pool.clone());
+    Ok(pool)
 }
 
-fn build_relative_file_path(path: &Path, root: &Path) -> String {
-    let root = root.to_str().unwrap();
-    let suffix = match root.ends_with('/') {
-        true => String::from(root),
-        false => String::from(root) + "/",
+fn build_rs_file(
+    root: &Path,
+    path: &Path,
+    options: &ScanOptions,
+    stat_cache: &StatCache,
+) -> Result<ScannedFile> {
+    let file_path = build_relative_file_path(path, root);
+    let metadata = std::fs::metadata(path)?;
+    let stat = FileStat::from(&metadata);
+    let file_metadata = FileMetadata::read(path, &metadata, options.metadata)?;
+    let cached_digest = match options.paranoid {
+        true => None,
+        false => stat_cache.get_digest(&file_path, &stat).cloned(),
     };
-    String::from(remove_prefix(path.to_str().unwrap(), &suffix))
+    let digest = match cached_digest {
+        Some(digest) => digest,
+        None => generate_digest(path, options.hash_algorithm)?,
+    };
+
+    let rs_file = RSFile {
+        metadata: file_metadata,
+        ..RSFile::new(file_path, digest, stat.size)
+    };
+    Ok((rs_file, Some(stat)))
+}
+
+/// Links are stored by their target, which is also what their digest is made of.
+fn build_rs_symlink(root: &Path, path: &Path, hash_algorithm: HashAlgorithm) -> Result<RSFile> {
+    let file_path = build_relative_file_path(path, root);
+    let target = encode_path(&std::fs::read_link(path)?);
+    let digest = generate_digest_from_bytes(target.as_bytes(), hash_algorithm);
+    Ok(RSFile {
+        kind: FileKind::Symlink { target },
+        ..RSFile::new(file_path, digest, 0)
+    })
 }
 
-fn remove_prefix<'a>(s: &'a str, suffix: &str) -> &'a str {
-    match s.strip_prefix(suffix) {
-        Some(s) => s,
-        None => s,
+fn build_rs_directory(root: &Path, path: &Path, hash_algorithm: HashAlgorithm) -> RSFile {
+    let file_path = build_relative_file_path(path, root);
+    let digest = generate_digest_from_bytes(&[], hash_algorithm);
+    RSFile {
+        kind: FileKind::Directory,
+        ..RSFile::new(file_path, digest, 0)
     }
 }
 
+/// Links are completed with the content of their target once every file is built.
+fn build_rs_hard_link(root: &Path, path: &Path, target: &Path) -> RSFile {
+    let file_path = build_relative_file_path(path, root);
+    RSFile {
+        kind: FileKind::HardLink {
+            target: build_relative_file_path(target, root),
+        },
+        ..RSFile::new(file_path, String::new(), 0)
+    }
+}
+
+/// Pairs new and removed files that share the same content, so they can be
+/// relinked by the server instead of being uploaded again.
+fn pair_moved_files(
+    new_files: Vec<RSFile>,
+    removed_files: Vec<RSFile>,
+) -> (Vec<RSFile>, Vec<MovedFile>, Vec<RSFile>) {
+    let mut removed_by_digest: HashMap<&str, Vec<usize>> = HashMap::new();
+    // indexes are pushed in reverse so `pop` hands out the first removed file
+    for (idx, file) in removed_files.iter().enumerate().rev() {
+        // empty files all share the same digest, pairing them is meaningless,
+        // and hard links have no content of their own to relink
+        if file.size > 0 && file.kind == FileKind::File {
+            removed_by_digest
+                .entry(file.digest.as_str())
+                .or_default()
+                .push(idx);
+        }
+    }
+
+    let mut is_paired = vec![false; removed_files.len()];
+    let mut remaining_new_files = Vec::new();
+    let mut moved_files = Vec::new();
+    for file in new_files {
+        let removed_file_idx = match file.kind {
+            FileKind::File => removed_by_digest
+                .get_mut(file.digest.as_str())
+                .and_then(Vec::pop),
+            _ => None,
+        };
+        match removed_file_idx {
+            Some(idx) => {
+                is_paired[idx] = true;
+                moved_files.push(MovedFile {
+                    from: removed_files[idx].clone(),
+                    to: file,
+                });
+            }
+            None => remaining_new_files.push(file),
+        }
+    }
+
+    let remaining_removed_files = removed_files
+        .into_iter()
+        .zip(is_paired)
+        .filter(|(_, is_paired)| !is_paired)
+        .map(|(file, _)| file)
+        .collect();
+    (remaining_new_files, moved_files, remaining_removed_files)
+}
+
+/// Checks for a `CACHEDIR.TAG` file, as defined by https://bford.info/cachedir/
+fn is_cache_dir(dir: &Path) -> bool {
+    let mut signature = [0_u8; CACHEDIR_TAG_SIGNATURE.len()];
+    std::fs::File::open(dir.join("CACHEDIR.TAG"))
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok_and(|_| &signature == CACHEDIR_TAG_SIGNATURE)
+}
+
+fn is_rs_dir(path: &Path, depth: u16) -> bool {
+    depth == 0 && path.file_name() == Some(OsStr::new(".rs"))
+}
+
+fn build_relative_file_path(path: &Path, root: &Path) -> String {
+    encode_path(path.strip_prefix(root).unwrap_or(path))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::model::fs_tree::RSFile;
-    use std::{path::PathBuf, str::FromStr};
+    use crate::model::{
+        digest::HashAlgorithm,
+        fs_tree::{
+            ExcludedEntry, ExclusionReason, ExclusionRules, FileKind, MovedFile, RSFile,
+            SymlinkPolicy,
+        },
+        ignore_rules::check_ignore,
+        stat_cache::{FileStat, StatCache},
+    };
+    use crate::testing::TempTree;
+    use std::{collections::HashSet, path::PathBuf, str::FromStr};
+
+    use super::{FSTree, ScanOptions};
 
-    use super::FSTree;
+    /// Options which don't depend on the global ignore file of whoever runs the tests.
+    fn scan_options() -> ScanOptions {
+        ScanOptions {
+            global_ignore_file: None,
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn file_diffing() {
         let path = PathBuf::from_str("./test-data").unwrap();
-        let old_fs_tree = FSTree::build(path, None).unwrap();
+        let old_fs_tree = FSTree::build(path, &scan_options(), None).unwrap();
         let mut fs_tree = old_fs_tree.clone();
         let removed_file = fs_tree.files.pop().unwrap();
-        let mut changed_file = &mut fs_tree.files[0];
-        changed_file.sha_256_digest =
+        let changed_file = &mut fs_tree.files[0];
+        changed_file.digest =
             String::from("982bc87271bad526f4659eb12ecf1fd1295ae9fe0acfcfc83539fb9c0e523f5e");
         let changed_file = changed_file.clone();
         let new_file = RSFile::new(
@@ -303,10 +1003,78 @@ mod tests {
         assert_eq!(files_diff.removed_files, vec![removed_file]);
     }
 
+    #[test]
+    fn detects_metadata_only_changes() {
+        let path = PathBuf::from_str("./test-data").unwrap();
+        let old_fs_tree = FSTree::build(path, &scan_options(), None).unwrap();
+        let mut fs_tree = old_fs_tree.clone();
+        fs_tree.files[0].metadata.mode = Some(0o755);
+        fs_tree.files[0].metadata.mtime += 60;
+
+        let files_diff = fs_tree.diff(&old_fs_tree).unwrap();
+
+        assert!(files_diff.changed_files.is_empty());
+        assert_eq!(
+            files_diff.metadata_changed_files,
+            vec![fs_tree.files[0].clone()]
+        );
+    }
+
+    #[test]
+    fn diff_keeps_tree_order() {
+        let build_tree = |paths: Vec<String>, digest: &str| FSTree {
+            root: PathBuf::from("/"),
+            hash_algorithm: Default::default(),
+            excluded: Vec::new(),
+            skipped: Vec::new(),
+            files: paths
+                .into_iter()
+                .map(|path| RSFile::new(path, digest.into(), 1))
+                .collect(),
+        };
+        let paths: Vec<String> = (0..10_000).rev().map(|idx| format!("{idx}")).collect();
+        let old_fs_tree = build_tree(paths[..6_000].to_vec(), "old");
+        let fs_tree = build_tree(paths[3_000..].to_vec(), "new");
+
+        let files_diff = fs_tree.diff(&old_fs_tree).unwrap();
+        let get_paths =
+            |files: &Vec<RSFile>| files.iter().map(|f| f.path.clone()).collect::<Vec<_>>();
+
+        assert_eq!(get_paths(&files_diff.removed_files), paths[..3_000]);
+        assert_eq!(get_paths(&files_diff.changed_files), paths[3_000..6_000]);
+        assert_eq!(get_paths(&files_diff.new_files), paths[6_000..]);
+    }
+
+    #[test]
+    fn detects_moved_files() {
+        let path = PathBuf::from_str("./test-data").unwrap();
+        let old_fs_tree = FSTree::build(path, &scan_options(), None).unwrap();
+        let mut fs_tree = old_fs_tree.clone();
+        let moved_from = fs_tree.files.pop().unwrap();
+        let moved_to = RSFile::new(
+            format!("archive/{}", moved_from.path),
+            moved_from.digest.clone(),
+            moved_from.size,
+        );
+        fs_tree.files.push(moved_to.clone());
+
+        let files_diff = fs_tree.diff(&old_fs_tree).unwrap();
+
+        assert!(files_diff.new_files.is_empty());
+        assert!(files_diff.removed_files.is_empty());
+        assert_eq!(
+            files_diff.moved_files,
+            vec![MovedFile {
+                from: moved_from,
+                to: moved_to
+            }]
+        );
+    }
+
     #[test]
     fn scans_a_directory_recursively() {
         let path = PathBuf::from_str("./test-data").unwrap();
-        let mut fs_tree = FSTree::build(path.clone(), None).unwrap();
+        let mut fs_tree = FSTree::build(path.clone(), &scan_options(), None).unwrap();
         let files = vec![
             RSFile::new(
                 String::from("other_folder/other_file.hs"),
@@ -319,11 +1087,468 @@ mod tests {
                 159_u64,
             ),
         ];
-        let mut target_fs_tree = FSTree { files, root: path };
+        let mut target_fs_tree = FSTree {
+            files,
+            root: path,
+            hash_algorithm: Default::default(),
+            excluded: Vec::new(),
+            skipped: Vec::new(),
+        };
         target_fs_tree.files.sort();
         fs_tree.files.sort();
+        // metadata depends on when the repository was checked out
+        for file in fs_tree.files.iter_mut() {
+            file.metadata = Default::default();
+        }
 
         assert_eq!(target_fs_tree.root, fs_tree.root);
         assert_eq!(target_fs_tree, fs_tree);
     }
+
+    #[test]
+    fn hashing_is_deterministic_regardless_of_thread_count() {
+        let path = PathBuf::from_str("./test-data").unwrap();
+        let single_threaded = FSTree::build(
+            path.clone(),
+            &ScanOptions {
+                hashing_threads: 1,
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+        let multi_threaded = FSTree::build(
+            path,
+            &ScanOptions {
+                hashing_threads: 4,
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(single_threaded, multi_threaded);
+    }
+
+    #[test]
+    fn reuses_cached_digests_unless_paranoid() {
+        let path = PathBuf::from_str("./test-data").unwrap();
+        let stat = FileStat::from(&std::fs::metadata(path.join("hello.ex")).unwrap());
+        let cached_digest = String::from("cached");
+        let mut stat_cache = StatCache::new(Default::default());
+        stat_cache.insert("hello.ex".into(), stat, cached_digest.clone());
+        let get_digest = |fs_tree: &FSTree| {
+            fs_tree
+                .files
+                .iter()
+                .find(|file| file.path == "hello.ex")
+                .map(|file| file.digest.clone())
+                .unwrap()
+        };
+
+        let (fs_tree, _) =
+            FSTree::build_with_cache(path.clone(), &scan_options(), &stat_cache, None).unwrap();
+        assert_eq!(get_digest(&fs_tree), cached_digest);
+
+        let paranoid = ScanOptions {
+            paranoid: true,
+            ..scan_options()
+        };
+        let (fs_tree, _) = FSTree::build_with_cache(path, &paranoid, &stat_cache, None).unwrap();
+        assert_ne!(get_digest(&fs_tree), cached_digest);
+    }
+
+    #[test]
+    fn hashes_with_the_chosen_algorithm() {
+        let path = PathBuf::from_str("./test-data").unwrap();
+        let options = ScanOptions {
+            hash_algorithm: HashAlgorithm::Blake3,
+            ..scan_options()
+        };
+        let (blake3_tree, _) = FSTree::build_with_cache(
+            path.clone(),
+            &options,
+            &StatCache::new(options.hash_algorithm),
+            None,
+        )
+        .unwrap();
+        let sha256_tree = FSTree::build(path.clone(), &scan_options(), None).unwrap();
+        let content = std::fs::read(path.join("hello.ex")).unwrap();
+        let blake3_file = blake3_tree
+            .files
+            .iter()
+            .find(|file| file.path == "hello.ex")
+            .unwrap();
+
+        assert_eq!(blake3_file.digest, blake3::hash(&content).to_hex().as_str());
+        assert!(blake3_tree.diff(&sha256_tree).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stores_symlinks_and_skips_loops_when_following() {
+        let tree = TempTree::new("symlinks");
+        tree.write("folder/file.txt", "content");
+        std::os::unix::fs::symlink("..", tree.path("folder/loop")).unwrap();
+        std::os::unix::fs::symlink("folder/file.txt", tree.path("link.txt")).unwrap();
+        std::os::unix::fs::symlink("folder", tree.path("linked")).unwrap();
+
+        let stored = FSTree::build(tree.root.clone(), &scan_options(), None).unwrap();
+        let follow = ScanOptions {
+            symlinks: SymlinkPolicy::Follow,
+            ..scan_options()
+        };
+        let followed = FSTree::build(tree.root.clone(), &follow, None).unwrap();
+
+        let get_entries = |fs_tree: &FSTree| {
+            fs_tree
+                .files
+                .iter()
+                .map(|file| (file.path.clone(), file.kind.clone()))
+                .collect::<Vec<_>>()
+        };
+        let symlink = |target: &str| FileKind::Symlink {
+            target: target.into(),
+        };
+        assert_eq!(
+            get_entries(&stored),
+            vec![
+                ("folder/file.txt".into(), FileKind::File),
+                ("folder/loop".into(), symlink("..")),
+                ("link.txt".into(), symlink("folder/file.txt")),
+                ("linked".into(), symlink("folder")),
+            ]
+        );
+        assert_eq!(
+            get_entries(&followed),
+            vec![
+                ("folder/file.txt".into(), FileKind::File),
+                ("link.txt".into(), FileKind::File),
+                ("linked/file.txt".into(), FileKind::File),
+            ]
+        );
+        assert!(stored.followed_links().is_empty());
+        assert_eq!(
+            followed.followed_links(),
+            HashSet::from([PathBuf::from("linked")])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stores_hard_links_once() {
+        let tree = TempTree::new("hard-links");
+        tree.write("cache/object", "content");
+        std::fs::hard_link(tree.path("cache/object"), tree.path("copy")).unwrap();
+
+        let fs_tree = FSTree::build(tree.root.clone(), &scan_options(), None).unwrap();
+
+        let object = &fs_tree.files[0];
+        let copy = &fs_tree.files[1];
+        assert_eq!(object.kind, FileKind::File);
+        assert_eq!(
+            copy.kind,
+            FileKind::HardLink {
+                target: "cache/object".into()
+            }
+        );
+        assert_eq!(copy.digest, object.digest);
+        assert_eq!(fs_tree.total_size(), 7);
+    }
+
+    #[test]
+    fn rsignore_files_follow_gitignore_semantics() {
+        let tree = TempTree::new("ignore");
+        for file in [
+            "app.log",
+            "keep.log",
+            "logs/build/out.txt",
+            "src/build/out.txt",
+        ] {
+            tree.write(file, "content");
+        }
+        tree.write(".rsignore", "*.log\n!keep.log\n");
+        tree.write("logs/.rsignore", "/build/\n");
+        let global_ignore_file = tree.path("global_ignore");
+        std::fs::write(&global_ignore_file, "global_ignore\n").unwrap();
+        let options = ScanOptions {
+            global_ignore_file: Some(global_ignore_file.clone()),
+            ..scan_options()
+        };
+
+        let fs_tree = FSTree::build(tree.root.clone(), &options, None).unwrap();
+        let parent_match = check_ignore(&tree.root, &tree.path("logs/build/out.txt"), &options)
+            .unwrap()
+            .unwrap();
+        let negated_match = check_ignore(&tree.root, &tree.path("keep.log"), &options)
+            .unwrap()
+            .unwrap();
+
+        let paths: Vec<String> = fs_tree.files.into_iter().map(|file| file.path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                ".rsignore",
+                "keep.log",
+                "logs/.rsignore",
+                "src/build/out.txt"
+            ]
+        );
+        assert_eq!(parent_match.path, tree.path("logs/build"));
+        assert_eq!(parent_match.file, Some(tree.path("logs/.rsignore")));
+        assert_eq!(parent_match.rule, "/build/");
+        assert!(negated_match.is_negated);
+        assert_eq!(negated_match.line, Some(2));
+    }
+
+    #[test]
+    fn honors_git_ignores_only_when_enabled() {
+        let tree = TempTree::new("git");
+        for file in ["keep.tmp", "other.tmp", "target/out.bin"] {
+            tree.write(file, "content");
+        }
+        tree.write(".gitignore", "*.tmp\ntarget/\n");
+        tree.write(".rsignore", "!keep.tmp\n");
+        let git_ignore = ScanOptions {
+            git_ignore: true,
+            ..scan_options()
+        };
+        let no_git_ignore = scan_options();
+
+        let with_git_ignore = FSTree::build(tree.root.clone(), &git_ignore, None).unwrap();
+        let without_git_ignore = FSTree::build(tree.root.clone(), &no_git_ignore, None).unwrap();
+
+        let get_paths = |fs_tree: FSTree| {
+            fs_tree
+                .files
+                .into_iter()
+                .map(|file| file.path)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            get_paths(with_git_ignore),
+            vec![".gitignore", ".rsignore", "keep.tmp"]
+        );
+        assert_eq!(
+            get_paths(without_git_ignore),
+            vec![
+                ".gitignore",
+                ".rsignore",
+                "keep.tmp",
+                "other.tmp",
+                "target/out.bin"
+            ]
+        );
+    }
+
+    #[test]
+    fn only_scans_allowlisted_paths() {
+        let tree = TempTree::new("include");
+        tree.create_dir(".config/nvim/lua");
+        for file in [
+            ".config/nvim/init.lua",
+            ".config/other/settings.json",
+            "notes/todo.md",
+            "notes/todo.txt",
+            "notes/drafts/idea.md",
+            "downloads.iso",
+        ] {
+            tree.write(file, "content");
+        }
+        tree.write(".rsinclude", "# notes\nnotes/*.md\n");
+        let options = ScanOptions {
+            include: vec!["/.config/nvim/".into()],
+            ..scan_options()
+        };
+
+        let fs_tree = FSTree::build(tree.root.clone(), &options, None).unwrap();
+
+        let paths: Vec<String> = fs_tree.files.into_iter().map(|file| file.path).collect();
+        assert_eq!(
+            paths,
+            vec![".config/nvim/init.lua", ".config/nvim/lua", "notes/todo.md"]
+        );
+    }
+
+    #[test]
+    fn skips_unreadable_entries_unless_strict() {
+        let tree = TempTree::new("skip");
+        tree.write("broken/.rsignore", "[\n");
+        tree.write("broken/file.txt", "content");
+        tree.write("file.txt", "content");
+        let old_fs_tree = FSTree {
+            files: vec![RSFile::new("broken/file.txt".into(), "digest".into(), 7)],
+            root: tree.root.clone(),
+            hash_algorithm: Default::default(),
+            excluded: Vec::new(),
+            skipped: Vec::new(),
+        };
+        let strict = ScanOptions {
+            strict: true,
+            ..scan_options()
+        };
+
+        let mut fs_tree = FSTree::build(tree.root.clone(), &scan_options(), None).unwrap();
+        let strict_result = FSTree::build(tree.root.clone(), &strict, None);
+
+        assert!(strict_result.is_err());
+        assert_eq!(fs_tree.skipped.len(), 1);
+        assert_eq!(fs_tree.skipped[0].path, "broken");
+        fs_tree.keep_unscanned_files(&old_fs_tree);
+        let diff = fs_tree.diff(&old_fs_tree).unwrap();
+        assert!(diff.removed_files.is_empty());
+        assert_eq!(diff.new_files.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_tracked_files_which_became_unreadable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tree = TempTree::new("unreadable");
+        tree.write("secret.txt", "content");
+        tree.write("file.txt", "content");
+        let old_fs_tree = FSTree::build(tree.root.clone(), &scan_options(), None).unwrap();
+        std::fs::set_permissions(
+            tree.path("secret.txt"),
+            std::fs::Permissions::from_mode(0o000),
+        )
+        .unwrap();
+        // permissions don't apply to root, which can read the file anyway
+        if std::fs::read(tree.path("secret.txt")).is_ok() {
+            return;
+        }
+
+        let mut fs_tree = FSTree::build(tree.root.clone(), &scan_options(), None).unwrap();
+
+        assert_eq!(fs_tree.skipped.len(), 1);
+        assert_eq!(fs_tree.skipped[0].path, "secret.txt");
+        fs_tree.keep_unscanned_files(&old_fs_tree);
+        let kept_file = fs_tree.files.iter().find(|file| file.path == "secret.txt");
+        assert_eq!(
+            kept_file,
+            old_fs_tree
+                .files
+                .iter()
+                .find(|file| file.path == "secret.txt")
+        );
+        assert!(!fs_tree.diff(&old_fs_tree).unwrap().has_changes());
+    }
+
+    #[test]
+    fn reports_entries_left_out_by_exclusion_rules() {
+        let tree = TempTree::new("exclude");
+        tree.write(
+            "cache/CACHEDIR.TAG",
+            "Signature: 8a477f597d28d172789f06886806bc55\n# a cache",
+        );
+        tree.write("big.img", vec![0_u8; 2048]);
+        tree.write("old.txt", "content");
+        tree.write("new.txt", "content");
+        let two_days_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(172_800);
+        std::fs::File::options()
+            .write(true)
+            .open(tree.path("old.txt"))
+            .unwrap()
+            .set_modified(two_days_ago)
+            .unwrap();
+        let options = ScanOptions {
+            exclusions: ExclusionRules {
+                max_file_size: Some(1024),
+                max_age_days: Some(1),
+                exclude_caches: true,
+                one_file_system: true,
+            },
+            ..scan_options()
+        };
+
+        let fs_tree = FSTree::build(tree.root.clone(), &options, None).unwrap();
+
+        let paths: Vec<String> = fs_tree.files.into_iter().map(|file| file.path).collect();
+        let excluded_entry = |path: &str, reason| ExcludedEntry {
+            path: path.into(),
+            reason,
+        };
+        assert_eq!(paths, vec!["new.txt"]);
+        assert_eq!(
+            fs_tree.excluded,
+            vec![
+                excluded_entry("big.img", ExclusionReason::TooLarge(2048)),
+                excluded_entry("cache", ExclusionReason::CacheDirectory),
+                excluded_entry("old.txt", ExclusionReason::TooOld),
+            ]
+        );
+    }
+
+    #[test]
+    fn excluded_files_which_were_tracked_are_not_removed() {
+        let tree = TempTree::new("exclude-kept");
+        tree.write("old.txt", "content");
+        tree.write("new.txt", "content");
+        let options = ScanOptions {
+            exclusions: ExclusionRules {
+                max_age_days: Some(1),
+                ..Default::default()
+            },
+            ..scan_options()
+        };
+        let old_fs_tree = FSTree::build(tree.root.clone(), &options, None).unwrap();
+        let two_days_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(172_800);
+        std::fs::File::options()
+            .write(true)
+            .open(tree.path("old.txt"))
+            .unwrap()
+            .set_modified(two_days_ago)
+            .unwrap();
+
+        let mut fs_tree = FSTree::build(tree.root.clone(), &options, None).unwrap();
+
+        assert_eq!(fs_tree.excluded.len(), 1);
+        fs_tree.keep_unscanned_files(&old_fs_tree);
+        let diff = fs_tree.diff(&old_fs_tree).unwrap();
+        assert!(!diff.has_changes());
+    }
+
+    #[test]
+    fn records_empty_directories() {
+        let tree = TempTree::new("dirs");
+        tree.create_dir("logs/archive");
+        tree.create_dir("tmp");
+        tree.write("logs/app.log", "content");
+
+        let fs_tree = FSTree::build(tree.root.clone(), &scan_options(), None).unwrap();
+
+        let entries = fs_tree
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.kind.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                ("logs/app.log", FileKind::File),
+                ("logs/archive", FileKind::Directory),
+                ("tmp", FileKind::Directory),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn encodes_non_utf8_file_names_losslessly() {
+        use crate::util::decode_path;
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let tree = TempTree::new("latin1");
+        let file_name = OsStr::from_bytes(b"caf\xe9 100%.txt");
+        tree.write(file_name, "content");
+
+        let fs_tree = FSTree::build(tree.root.clone(), &scan_options(), None).unwrap();
+
+        assert_eq!(fs_tree.files[0].path, "./caf%E9 100%25.txt");
+        assert_eq!(
+            decode_path(&fs_tree.files[0].path),
+            PathBuf::from(file_name)
+        );
+    }
 }
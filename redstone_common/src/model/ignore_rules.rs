@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder, Glob},
+    Match,
+};
+
+use super::{fs_tree::ScanOptions, ArgumentError, RedstoneError, Result};
+
+/// Name of the per-folder ignore file, which follows the `.gitignore` format.
+pub const IGNORE_FILE_NAME: &str = ".rsignore";
+
+/// Files honored when git ignores are enabled, from the lowest to the highest precedence.
+const GIT_IGNORE_FILES: [&str; 3] = [".git/info/exclude", ".gitignore", ".ignore"];
+
+/// Ignore rules in effect while walking a backup, top to bottom.
+///
+/// Every `.rsignore` applies to its own folder, patterns are relative to it and the
+/// deepest file which has an opinion about a path wins, exactly like `.gitignore`.
+/// The global ignore file has the lowest precedence and is relative to the backup root.
+///
+/// When enabled, git's ignore files are only looked at for paths which none of
+/// Redstone's own files have an opinion about.
+pub struct IgnoreRules {
+    global: Gitignore,
+    /// Matchers of the folders currently being walked, outermost first.
+    folders: Vec<Gitignore>,
+    git_ignore: bool,
+    git_global: Gitignore,
+    git_folders: Vec<Gitignore>,
+}
+
+/// The rule which decided whether a path is ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoreMatch {
+    /// Path the rule matched, which is a parent folder when the whole folder was ignored.
+    pub path: PathBuf,
+    /// Ignore file where the rule was defined.
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    /// The rule as written in the file.
+    pub rule: String,
+    /// Whether the rule re-includes the path (a `!` rule).
+    pub is_negated: bool,
+}
+
+impl IgnoreMatch {
+    fn new(path: &Path, glob: &Glob) -> Self {
+        let file = glob.from().map(Path::to_path_buf);
+        let line = file
+            .as_ref()
+            .and_then(|file| find_rule_line(file, glob.original()));
+        Self {
+            path: path.to_path_buf(),
+            file,
+            line,
+            rule: glob.original().to_owned(),
+            is_negated: glob.is_whitelist(),
+        }
+    }
+}
+
+impl IgnoreRules {
+    pub fn new(root: &Path, options: &ScanOptions) -> Result<Self> {
+        let global = build_matcher(root, options.global_ignore_file.as_slice())?;
+        // a missing or broken global git config shouldn't prevent backups
+        let git_global = match options.git_ignore {
+            true => Gitignore::global().0,
+            false => Gitignore::empty(),
+        };
+        Ok(Self {
+            global,
+            folders: Vec::new(),
+            git_ignore: options.git_ignore,
+            git_global,
+            git_folders: Vec::new(),
+        })
+    }
+
+    /// Loads the rules of a folder, which apply until `leave_dir` is called.
+    pub fn enter_dir(&mut self, dir: &Path) -> Result<()> {
+        // both matchers are built before pushing, so a broken file leaves the stacks untouched
+        let matcher = build_matcher(dir, &[dir.join(IGNORE_FILE_NAME)])?;
+        if self.git_ignore {
+            let git_ignore_files = GIT_IGNORE_FILES.map(|file| dir.join(file));
+            self.git_folders
+                .push(build_matcher(dir, &git_ignore_files)?);
+        }
+        self.folders.push(matcher);
+        Ok(())
+    }
+
+    pub fn leave_dir(&mut self) {
+        self.folders.pop();
+        self.git_folders.pop();
+    }
+
+    /// Finds the rule which decides whether a path directly inside of the current folder
+    /// is ignored, if there's any.
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Option<IgnoreMatch> {
+        self.folders
+            .iter()
+            .rev()
+            .chain(std::iter::once(&self.global))
+            .chain(self.git_folders.iter().rev())
+            .chain(std::iter::once(&self.git_global))
+            .find_map(|matcher| match matcher.matched(path, is_dir) {
+                Match::None => None,
+                Match::Ignore(glob) | Match::Whitelist(glob) => Some(IgnoreMatch::new(path, glob)),
+            })
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.matched(path, is_dir)
+            .is_some_and(|matched| !matched.is_negated)
+    }
+}
+
+/// Explains why a path inside of `root` is or isn't ignored.
+///
+/// Folders are never walked into once they're ignored, so a path inside of one is
+/// reported as matched by the folder's rule, even if a later rule would re-include it.
+pub fn check_ignore(
+    root: &Path,
+    path: &Path,
+    options: &ScanOptions,
+) -> Result<Option<IgnoreMatch>> {
+    let Ok(relative_path) = path.strip_prefix(root) else {
+        return Err(RedstoneError::ArgumentError(ArgumentError::InvalidPath(
+            path.to_string_lossy().into(),
+        )));
+    };
+    let mut rules = IgnoreRules::new(root, options)?;
+    rules.enter_dir(root)?;
+
+    let mut current_path = root.to_path_buf();
+    let mut components = relative_path.components().peekable();
+    while let Some(component) = components.next() {
+        current_path.push(component);
+        let is_dir = current_path.is_dir();
+        let matched = rules.matched(&current_path, is_dir);
+        if components.peek().is_none() {
+            return Ok(matched);
+        }
+        if let Some(matched) = matched.filter(|matched| !matched.is_negated) {
+            return Ok(Some(matched));
+        }
+        rules.enter_dir(&current_path)?;
+    }
+    Ok(None)
+}
+
+/// Builds a matcher out of the ignore files which exist, later files taking precedence.
+fn build_matcher(root: &Path, ignore_files: &[PathBuf]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for ignore_file in ignore_files.iter().filter(|file| file.is_file()) {
+        if let Some(err) = builder.add(ignore_file) {
+            return Err(err.into());
+        }
+    }
+    Ok(builder.build()?)
+}
+
+fn find_rule_line(file: &Path, rule: &str) -> Option<usize> {
+    let content = std::fs::read_to_string(file).ok()?;
+    content
+        .lines()
+        .position(|line| line.trim_end() == rule)
+        .map(|idx| idx + 1)
+}
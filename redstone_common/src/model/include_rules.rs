@@ -0,0 +1,102 @@
+use std::{
+    ffi::OsStr,
+    path::{Component, Path},
+};
+
+use globset::{Glob, GlobMatcher};
+
+use super::Result;
+
+/// Name of the allowlist file, read from the backup's root.
+pub const INCLUDE_FILE_NAME: &str = ".rsinclude";
+
+/// Allowlist of paths, relative to the backup's root, which are the only ones scanned.
+///
+/// Patterns are matched one path component at a time, so the walker can tell which
+/// folders may lead to an included path without having to look inside of the others.
+pub struct IncludeRules {
+    patterns: Vec<Vec<PatternComponent>>,
+}
+
+enum PatternComponent {
+    Glob(GlobMatcher),
+    /// `**`, matching any amount of components.
+    AnyDepth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Inclusion {
+    Excluded,
+    /// The folder isn't included, but some of its content may be.
+    Descend,
+    /// The path and everything inside of it are included.
+    Included,
+}
+
+impl IncludeRules {
+    /// Builds the rules out of the given patterns and the root's `.rsinclude` file,
+    /// returning `None` when there are no patterns at all, since everything is included then.
+    pub fn new(root: &Path, patterns: &[String]) -> Result<Option<Self>> {
+        let include_file = root.join(INCLUDE_FILE_NAME);
+        let file_patterns = match include_file.is_file() {
+            true => std::fs::read_to_string(include_file)?,
+            false => String::new(),
+        };
+        let patterns = patterns
+            .iter()
+            .map(String::as_str)
+            .chain(file_patterns.lines())
+            .map(|pattern| pattern.trim().trim_matches('/'))
+            .filter(|pattern| !pattern.is_empty() && !pattern.starts_with('#'))
+            .map(parse_pattern)
+            .collect::<Result<Vec<_>>>()?;
+
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self { patterns }))
+    }
+
+    pub fn check(&self, relative_path: &Path) -> Inclusion {
+        let components: Vec<&OsStr> = relative_path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        self.patterns
+            .iter()
+            .map(|pattern| match_components(pattern, &components))
+            .max()
+            .unwrap_or(Inclusion::Excluded)
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Result<Vec<PatternComponent>> {
+    pattern
+        .split('/')
+        .map(|component| match component {
+            "**" => Ok(PatternComponent::AnyDepth),
+            component => Ok(PatternComponent::Glob(
+                Glob::new(component)?.compile_matcher(),
+            )),
+        })
+        .collect()
+}
+
+fn match_components(pattern: &[PatternComponent], path: &[&OsStr]) -> Inclusion {
+    match (pattern.split_first(), path.split_first()) {
+        (None, _) => Inclusion::Included,
+        (Some(_), None) => Inclusion::Descend,
+        (Some((PatternComponent::AnyDepth, remaining_pattern)), Some((_, remaining_path))) => {
+            match_components(remaining_pattern, path).max(match_components(pattern, remaining_path))
+        }
+        (Some((PatternComponent::Glob(glob), remaining_pattern)), Some((name, remaining_path))) => {
+            match glob.is_match(name) {
+                true => match_components(remaining_pattern, remaining_path),
+                false => Inclusion::Excluded,
+            }
+        }
+    }
+}
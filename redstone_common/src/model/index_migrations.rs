@@ -0,0 +1,98 @@
+//! Upgrades indexes written by older clients to the current format.
+//!
+//! Every format version which can still be read keeps its own copy of the structs it
+//! was written with, since bincode can't skip or default fields on its own.
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use super::{
+    api::{self, Update},
+    backup::{BackupConfig, IndexFile, INDEX_FORMAT_VERSION},
+    fs_tree::{FSTree, RSFile},
+    DomainError, RedstoneError, Result,
+};
+
+/// Reads the index out of a payload of the given format version, refusing the
+/// versions written by newer clients.
+pub fn migrate(version: u32, payload: &[u8]) -> Result<IndexFile> {
+    match version {
+        0 => Ok(bincode::deserialize::<v0::IndexFile>(payload)?.into()),
+        INDEX_FORMAT_VERSION => Ok(bincode::deserialize(payload)?),
+        version => Err(RedstoneError::DomainError(
+            DomainError::IndexFromNewerClient(version),
+        )),
+    }
+}
+
+/// Indexes written before the header was introduced.
+mod v0 {
+    use super::{Deserialize, PathBuf, Update};
+
+    #[derive(Deserialize)]
+    pub struct IndexFile {
+        pub config: BackupConfig,
+        pub backup: Backup,
+        pub last_fs_tree: FSTree,
+        pub current_update: Update,
+        pub latest_update: Update,
+    }
+
+    #[derive(Deserialize)]
+    pub struct BackupConfig {
+        pub sync_every: Option<String>,
+        pub watch: bool,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Backup {
+        pub id: String,
+        pub name: String,
+        pub entrypoint: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct FSTree {
+        pub files: Vec<RSFile>,
+        pub root: PathBuf,
+    }
+
+    #[derive(Deserialize)]
+    pub struct RSFile {
+        pub path: String,
+        pub sha_256_digest: String,
+        pub size: u64,
+    }
+}
+
+impl From<v0::IndexFile> for IndexFile {
+    fn from(index_file: v0::IndexFile) -> Self {
+        let backup = api::Backup {
+            id: index_file.backup.id,
+            name: index_file.backup.name,
+            entrypoint: index_file.backup.entrypoint,
+            hash_algorithm: Default::default(),
+        };
+        let fs_tree = FSTree {
+            files: index_file
+                .last_fs_tree
+                .files
+                .into_iter()
+                .map(|file| RSFile::new(file.path, file.sha_256_digest, file.size))
+                .collect(),
+            root: index_file.last_fs_tree.root,
+            hash_algorithm: Default::default(),
+            excluded: Vec::new(),
+            skipped: Vec::new(),
+        };
+        let config = BackupConfig::new(index_file.config.sync_every, index_file.config.watch);
+        IndexFile::new(
+            backup,
+            index_file.current_update,
+            index_file.latest_update,
+            config,
+            fs_tree,
+        )
+    }
+}
@@ -2,8 +2,14 @@ use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use crate::model::tcp::TransferOptions;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CloneRequest {
+    #[serde(with = "crate::util::encoded_path")]
     pub path: PathBuf,
     pub backup_name: String,
+    /// Wait for the backup's lock instead of failing when it's busy.
+    pub wait: bool,
+    pub transfer: TransferOptions,
 }
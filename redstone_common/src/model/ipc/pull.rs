@@ -2,7 +2,13 @@ use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use crate::model::tcp::TransferOptions;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PullRequest {
+    #[serde(with = "crate::util::encoded_path")]
     pub path: PathBuf,
+    /// Wait for the backup's lock instead of failing when it's busy.
+    pub wait: bool,
+    pub transfer: TransferOptions,
 }
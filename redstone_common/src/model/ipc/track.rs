@@ -2,11 +2,30 @@ use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use crate::model::{
+    digest::HashAlgorithm,
+    fs_tree::{ExclusionRules, SymlinkPolicy},
+    tcp::TransferOptions,
+};
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TrackRequest {
+    #[serde(with = "crate::util::encoded_path")]
     pub base_path: PathBuf,
     pub name: String,
     pub detatched: bool,
     pub sync_every: Option<String>,
     pub watch: bool,
+    pub hashing_threads: Option<usize>,
+    pub preserve_ownership: bool,
+    pub preserve_xattrs: bool,
+    pub symlinks: SymlinkPolicy,
+    pub hash_algorithm: HashAlgorithm,
+    pub exclusions: ExclusionRules,
+    pub git_ignore: bool,
+    pub include: Vec<String>,
+    pub strict: bool,
+    /// Wait for the backup's lock instead of failing when it's busy.
+    pub wait: bool,
+    pub transfer: TransferOptions,
 }
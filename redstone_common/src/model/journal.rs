@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::write_file_atomically;
+
+use super::{
+    api::{FileUploadRequest, UploadResponse},
+    fs_tree::FSTree,
+    Result,
+};
+
+/// Operation which started changing the backup, kept in `.rs/journal` until its
+/// index is saved, so the next command can tell it was interrupted.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum JournalEntry {
+    /// Files are being uploaded on top of `base_update_id`, keeping what the server
+    /// answered to the push request so the upload can be resumed, and the tree which
+    /// gets indexed once it's committed.
+    Push {
+        base_update_id: String,
+        files: Vec<FileUploadRequest>,
+        upload: Box<UploadResponse>,
+        fs_tree: Box<FSTree>,
+    },
+    /// Files of `update_id` are being downloaded over the local ones.
+    Pull {
+        update_id: String,
+    },
+    Clone {
+        update_id: String,
+    },
+}
+
+impl JournalEntry {
+    pub fn from_file(path: &Path) -> Result<Option<Self>> {
+        match std::fs::read(path) {
+            Ok(content) => Ok(Some(bincode::deserialize(&content)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_file_atomically(path, &bincode::serialize(self)?)
+    }
+
+    /// Marks the operation as finished.
+    pub fn clear(path: &Path) -> Result<()> {
+        match std::fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Explains what the interrupted operation left behind.
+    pub fn get_interrupted_message(&self) -> String {
+        match self {
+            Self::Push { .. } => "\
+            A previous push was interrupted, the next push resumes it if no files changed since.\
+            \nOtherwise it'll be settled by the next push or pull."
+                .into(),
+            Self::Pull { .. } => "\
+            A previous pull was interrupted, so some files may not be updated yet.\
+            \nRun \"redstone pull\" again to finish it."
+                .into(),
+            Self::Clone { .. } => "\
+            A previous clone was interrupted, so some files may be missing.\
+            \nRun \"redstone clone\" again to finish it."
+                .into(),
+        }
+    }
+}
+
+pub fn get_journal_file_for_path(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    path.push(".rs");
+    path.push("journal");
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_journal_file_for_path, JournalEntry};
+    use crate::testing::TempTree;
+
+    #[test]
+    fn journal_is_kept_until_cleared() {
+        let tree = TempTree::new("journal");
+        let path = get_journal_file_for_path(&tree.root);
+
+        let empty = JournalEntry::from_file(&path).unwrap();
+        JournalEntry::Pull {
+            update_id: "update".into(),
+        }
+        .save(&path)
+        .unwrap();
+        let saved = JournalEntry::from_file(&path).unwrap();
+        JournalEntry::clear(&path).unwrap();
+        let cleared = JournalEntry::from_file(&path).unwrap();
+        // clearing twice is fine, as finished operations clear it whether or not it's there
+        let cleared_again = JournalEntry::clear(&path);
+
+        assert!(empty.is_none());
+        assert!(matches!(saved, Some(JournalEntry::Pull { update_id }) if update_id == "update"));
+        assert!(cleared.is_none());
+        assert!(cleared_again.is_ok());
+    }
+}
@@ -7,9 +7,17 @@ use crate::web::api::ApiErrorResponse;
 
 pub mod api;
 pub mod backup;
+pub mod backup_lock;
 pub mod config;
+pub mod digest;
+pub mod file_metadata;
 pub mod fs_tree;
+pub mod ignore_rules;
+pub mod include_rules;
+mod index_migrations;
 pub mod ipc;
+pub mod journal;
+pub mod stat_cache;
 pub mod tcp;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -17,6 +25,7 @@ pub enum RedstoneError {
     ApiError(ApiErrorResponse),
     ArgumentError(ArgumentError),
     BaseError(String),
+    ConnectionLost(String),
     ConnectionTimeout,
     CronParseError(String),
     DomainError(DomainError),
@@ -83,6 +92,18 @@ impl From<reqwest::Error> for RedstoneError {
     }
 }
 
+impl From<rayon::ThreadPoolBuildError> for RedstoneError {
+    fn from(error: rayon::ThreadPoolBuildError) -> Self {
+        RedstoneError::BaseError(error.to_string())
+    }
+}
+
+impl From<globset::Error> for RedstoneError {
+    fn from(error: globset::Error) -> Self {
+        RedstoneError::BaseError(error.to_string())
+    }
+}
+
 impl From<ignore::Error> for RedstoneError {
     fn from(error: ignore::Error) -> Self {
         RedstoneError::IOError(error.to_string())
@@ -96,6 +117,7 @@ impl Display for RedstoneError {
             Self::BaseError(error) => error.to_owned(),
             Self::ArgumentError(error) => error.to_string(),
             Self::DomainError(error) => error.to_string(),
+            Self::ConnectionLost(reason) => format!("Lost the connection to the server: {reason}"),
             Self::ConnectionTimeout => String::from("Connection timed out."),
             Self::CronParseError(cron) => format!("Couldn't parse cron string: {cron}"),
             Self::IOError(reason) => reason.to_string(),
@@ -143,6 +165,12 @@ pub enum DomainError {
     NoChanges,
     ConfirmationNotAccepted,
     ErrorDurringProgressEmition,
+    FileChangedDuringBackup(String),
+    UnsafeRemotePaths(Vec<String>),
+    IndexFromNewerClient(u32),
+    /// The backup is locked, by the operation when it's known.
+    BackupBusy(Option<String>),
+    DownloadedFileMismatch(String),
 }
 
 impl Display for DomainError {
@@ -167,6 +195,43 @@ impl Display for DomainError {
             .into(),
             Self::ConfirmationNotAccepted => "".into(),
             Self::ErrorDurringProgressEmition => "".into(),
+            Self::FileChangedDuringBackup(path) => format!(
+                "\
+            \n\"{path}\" changed during backup, so the update was discarded.\
+            \n\nPlease, run the command again once it's no longer being modified.\
+            "
+            ),
+            Self::UnsafeRemotePaths(paths) => format!(
+                "Refused paths sent by the server which point outside of the backup:\n{}",
+                paths
+                    .iter()
+                    .map(|path| format!("\t{path}\n"))
+                    .collect::<String>()
+            ),
+            Self::IndexFromNewerClient(version) => format!(
+                "\
+            \nThis backup's index was written by a newer version of Redstone (format {version}).\
+            \n\nPlease, update Redstone to keep using this backup.\
+            "
+            ),
+            Self::BackupBusy(holder) => {
+                let holder = match holder {
+                    Some(operation) => format!(" by {operation}"),
+                    None => String::new(),
+                };
+                format!(
+                    "\
+            \nThe backup is being used{holder}.\
+            \n\nTry again once it's done, or use --wait to wait for it.\
+            "
+                )
+            }
+            Self::DownloadedFileMismatch(path) => format!(
+                "\
+            \n\"{path}\" doesn't match the digest sent by the server, so it was left untouched.\
+            \n\nPlease, run the command again to download it once more.\
+            "
+            ),
             Self::NotAuthenticated => "Not authenticated, run redstone auth to authenticate".into(),
             Self::NoServerConfigFound => {
                 "No server configuration found. Use the command: redstone set-server-address".into()
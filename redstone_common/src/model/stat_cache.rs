@@ -0,0 +1,113 @@
+use std::{
+    collections::HashMap,
+    fs::Metadata,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::write_file_atomically;
+
+use super::{digest::HashAlgorithm, Result};
+
+/// Stat data used to tell whether a file may have changed since it was last hashed.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct FileStat {
+    pub device: u64,
+    pub inode: u64,
+    pub size: u64,
+    pub mtime: i64,
+    pub mtime_nsec: i64,
+    pub ctime: i64,
+    pub ctime_nsec: i64,
+}
+
+#[cfg(unix)]
+impl From<&Metadata> for FileStat {
+    fn from(metadata: &Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        Self {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+            size: metadata.size(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            ctime: metadata.ctime(),
+            ctime_nsec: metadata.ctime_nsec(),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl From<&Metadata> for FileStat {
+    fn from(metadata: &Metadata) -> Self {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Self {
+            device: 0,
+            inode: 0,
+            size: metadata.len(),
+            mtime: mtime.as_secs() as i64,
+            mtime_nsec: mtime.subsec_nanos() as i64,
+            ctime: 0,
+            ctime_nsec: 0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatCacheEntry {
+    pub stat: FileStat,
+    pub digest: String,
+}
+
+/// Digests of the last scan, keyed by the file's relative path.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatCache {
+    hash_algorithm: HashAlgorithm,
+    entries: HashMap<String, StatCacheEntry>,
+}
+
+impl StatCache {
+    pub fn new(hash_algorithm: HashAlgorithm) -> Self {
+        Self {
+            hash_algorithm,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Reads the cache, falling back to an empty one if it's missing, unreadable or
+    /// made with another algorithm, since it can always be rebuilt by hashing the files again.
+    pub fn from_file(path: &Path, hash_algorithm: HashAlgorithm) -> Self {
+        std::fs::read(path)
+            .ok()
+            .and_then(|content| bincode::deserialize::<Self>(&content).ok())
+            .filter(|stat_cache| stat_cache.hash_algorithm == hash_algorithm)
+            .unwrap_or_else(|| Self::new(hash_algorithm))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_file_atomically(path, &bincode::serialize(self)?)
+    }
+
+    pub fn get_digest(&self, path: &str, stat: &FileStat) -> Option<&String> {
+        self.entries
+            .get(path)
+            .filter(|entry| entry.stat == *stat)
+            .map(|entry| &entry.digest)
+    }
+
+    pub fn insert(&mut self, path: String, stat: FileStat, digest: String) {
+        self.entries.insert(path, StatCacheEntry { stat, digest });
+    }
+}
+
+pub fn get_stat_cache_file_for_path(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    path.push(".rs");
+    path.push("stat_cache");
+    path
+}
@@ -1,5 +1,6 @@
 /// TCP message models
 use super::Result;
+use crate::constants::{DEFAULT_TRANSFER_CONNECTIONS, DEFAULT_UPLOAD_WINDOW};
 use serde::{Deserialize, Serialize};
 
 pub trait TcpMessage {
@@ -29,7 +30,9 @@ pub struct DownloadChunkMessage {
     pub download_token: String,
     pub operation: TcpOperation,
     pub file_id: String,
-    pub offset: usize,
+    /// Index of the requested chunk, as the file is split in chunks of `byte_limit` bytes.
+    #[serde(rename = "offset")]
+    pub chunk_index: usize,
     pub byte_limit: usize,
 }
 
@@ -54,11 +57,58 @@ pub struct FileUploadMessage {
     pub operation: TcpOperation,
     pub file_id: String,
     pub file_size: usize,
+    /// Position of `data` in the file.
+    pub offset: usize,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+    pub last_chunk: bool,
+}
+
+/// Data of the response to an uploaded chunk, telling which chunk it refers to,
+/// since more chunks may be sent before it arrives.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct UploadChunkAck {
+    pub file_id: String,
+    pub offset: usize,
+}
+
+/// Data of the response to a `DownloadChunk`, telling where the chunk belongs since
+/// its length alone can't tell whether the file is over.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct DownloadChunk {
+    pub file_id: String,
+    /// Position of `data` in the file.
+    pub offset: usize,
+    pub total_size: usize,
     #[serde(with = "serde_bytes")]
     pub data: Vec<u8>,
     pub last_chunk: bool,
 }
 
+/// Data of the response to `CheckFile`, telling how much of the file the server holds.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct FileCheck {
+    pub received_bytes: usize,
+}
+
+/// How files are transferred to and from the server.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct TransferOptions {
+    /// Amount of chunks which may be sent before the first of them is acknowledged.
+    pub upload_window: usize,
+    /// Amount of connections files are spread over, all of them sharing the same token.
+    pub connections: usize,
+}
+
+impl Default for TransferOptions {
+    fn default() -> Self {
+        Self {
+            upload_window: DEFAULT_UPLOAD_WINDOW,
+            connections: DEFAULT_TRANSFER_CONNECTIONS,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct CommitMessage {
     pub upload_token: String,
@@ -1,27 +1,51 @@
-use std::{io::Read, path::Path};
+use std::{
+    collections::HashSet,
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
 
-use data_encoding::HEXLOWER;
-use sha2::{Digest, Sha256};
+use crate::{
+    constants::HASH_BUFFER_SIZE,
+    model::{digest::HashAlgorithm, DomainError, RedstoneError, Result},
+};
 
-use crate::model::Result;
+pub fn generate_digest(path: &Path, algorithm: HashAlgorithm) -> Result<String> {
+    let mut input = std::fs::File::open(path)?;
+    let mut hasher = algorithm.hasher();
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        let count = input.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+    Ok(hasher.finalize())
+}
 
-pub fn generate_sha256_digest(path: &Path) -> Result<String> {
-    let input = std::fs::File::open(path)?;
-    let mut reader = std::io::BufReader::new(input);
+/// Replaces the file's content through a synced temporary file, so a crash
+/// leaves either the old or the new content, never a truncated file.
+///
+/// The temporary file has a unique name, so concurrent writers never share it.
+pub fn write_file_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut file = tempfile::NamedTempFile::new_in(parent)?;
+    file.write_all(content)?;
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|err| err.error)?;
+    // the rename itself is only durable once the folder is synced
+    #[cfg(unix)]
+    std::fs::File::open(parent)?.sync_all()?;
+    Ok(())
+}
 
-    let digest = {
-        let mut hasher = Sha256::new();
-        let mut buffer = [0; 1024];
-        loop {
-            let count = reader.read(&mut buffer)?;
-            if count == 0 {
-                break;
-            }
-            hasher.update(&buffer[..count]);
-        }
-        hasher.finalize()
-    };
-    Ok(HEXLOWER.encode(digest.as_ref()))
+pub fn generate_digest_from_bytes(data: &[u8], algorithm: HashAlgorithm) -> String {
+    let mut hasher = algorithm.hasher();
+    hasher.update(data);
+    hasher.finalize()
 }
 
 pub fn bytes_to_human_readable(bytes: usize) -> String {
@@ -34,3 +58,317 @@ pub fn bytes_to_human_readable(bytes: usize) -> String {
     }
     format!("{:.2} {}", bytes, units[unit])
 }
+
+/// Parses sizes such as `512`, `100KB` or `30G`, the units being powers of 1024.
+pub fn human_readable_to_bytes(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let unit_start = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (amount, unit) = size.split_at(unit_start);
+    let exponent = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" => 1,
+        "M" | "MB" => 2,
+        "G" | "GB" => 3,
+        "T" | "TB" => 4,
+        _ => return None,
+    };
+    amount
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(1024_u64.pow(exponent))
+}
+
+/// Prefix of encoded paths.
+///
+/// Scanned paths never start with a `.` component, so it can't be mistaken for an actual
+/// file name, and a path which misses decoding still stays inside of the backup root.
+/// Symlink targets may start with it though, so those are always encoded.
+const ENCODED_PATH_PREFIX: &str = "./";
+
+/// Encodes a path into a string without losing any bytes.
+///
+/// Valid UTF-8 paths are kept as they are, unless they start with `./`. Otherwise, the
+/// path is prefixed by `./`, its invalid bytes are written as `%XX` and any literal `%`
+/// is written as `%25`.
+#[cfg(unix)]
+pub fn encode_path(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    if let Some(path) = path
+        .to_str()
+        .filter(|path| !path.starts_with(ENCODED_PATH_PREFIX))
+    {
+        return path.to_owned();
+    }
+    let mut encoded = String::from(ENCODED_PATH_PREFIX);
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        encoded += &chunk.valid().replace('%', "%25");
+        for byte in chunk.invalid() {
+            encoded += &format!("%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+#[cfg(not(unix))]
+pub fn encode_path(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// Reverts `encode_path`.
+#[cfg(unix)]
+pub fn decode_path(path: &str) -> PathBuf {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    let Some(encoded) = path.strip_prefix(ENCODED_PATH_PREFIX) else {
+        return PathBuf::from(path);
+    };
+    let encoded = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut idx = 0;
+    while idx < encoded.len() {
+        let escaped_byte = encoded
+            .get(idx + 1..idx + 3)
+            .filter(|_| encoded[idx] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped_byte {
+            Some(byte) => {
+                decoded.push(byte);
+                idx += 3;
+            }
+            None => {
+                decoded.push(encoded[idx]);
+                idx += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(decoded))
+}
+
+#[cfg(not(unix))]
+pub fn decode_path(path: &str) -> PathBuf {
+    PathBuf::from(path)
+}
+
+/// Serializes a path through `encode_path`, as serde refuses paths which aren't valid
+/// UTF-8, to be used as `#[serde(with = "crate::util::encoded_path")]`.
+///
+/// Valid UTF-8 absolute paths are serialized as they were before.
+pub mod encoded_path {
+    use std::path::{Path, PathBuf};
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::{decode_path, encode_path};
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode_path(path))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        String::deserialize(deserializer).map(|path| decode_path(&path))
+    }
+}
+
+/// Resolves a path received from the server to where it belongs inside of `root`.
+///
+/// Paths which could reach outside of the backup are refused: absolute ones, ones with
+/// `..` components and ones whose folder ends up outside of `root` through a symlink,
+/// unless it's inside of one of the `followed_links` (relative to `root`), which the
+/// last scan found the backup's files through.
+pub fn resolve_remote_path(
+    root: &Path,
+    path: &str,
+    followed_links: &HashSet<PathBuf>,
+) -> Result<PathBuf> {
+    let resolved_path = root.join(normalize_remote_path(path)?);
+    let folder = canonicalize_existing(resolved_path.parent().unwrap_or(root))
+        .map_err(|_| unsafe_remote_path_error(path))?;
+    let is_inside_backup = std::iter::once(root.to_path_buf())
+        .chain(followed_links.iter().map(|link| root.join(link)))
+        .filter_map(|allowed_folder| canonicalize_existing(&allowed_folder).ok())
+        .any(|allowed_folder| folder.starts_with(allowed_folder));
+    if !is_inside_backup {
+        return Err(unsafe_remote_path_error(path));
+    }
+    Ok(resolved_path)
+}
+
+/// Canonicalizes the deepest part of the path which exists, as the folders of a
+/// downloaded file may not have been created yet.
+fn canonicalize_existing(path: &Path) -> Result<PathBuf> {
+    let mut missing_components = Vec::new();
+    let mut existing_path = path;
+    loop {
+        match existing_path.canonicalize() {
+            Ok(canonical_path) => {
+                return Ok(missing_components
+                    .into_iter()
+                    .rev()
+                    .fold(canonical_path, |path, component| path.join(component)))
+            }
+            // a dangling symlink exists, and would be written through
+            Err(err) if existing_path.symlink_metadata().is_ok() => return Err(err.into()),
+            Err(err) => match (existing_path.parent(), existing_path.file_name()) {
+                (Some(parent), Some(file_name)) => {
+                    missing_components.push(file_name);
+                    existing_path = parent;
+                }
+                _ => return Err(err.into()),
+            },
+        }
+    }
+}
+
+/// Decodes a path received from the server, dropping its `.` components, as long as
+/// it stays relative to the backup's root.
+pub fn normalize_remote_path(path: &str) -> Result<PathBuf> {
+    let mut normalized_path = PathBuf::new();
+    for component in decode_path(path).components() {
+        match component {
+            Component::Normal(name) => normalized_path.push(name),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(unsafe_remote_path_error(path))
+            }
+        }
+    }
+    if normalized_path.as_os_str().is_empty() {
+        return Err(unsafe_remote_path_error(path));
+    }
+    Ok(normalized_path)
+}
+
+fn unsafe_remote_path_error(path: &str) -> RedstoneError {
+    RedstoneError::DomainError(DomainError::UnsafeRemotePaths(vec![path.to_owned()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::TempTree;
+    use std::{
+        collections::HashSet,
+        path::{Path, PathBuf},
+    };
+
+    use super::{
+        decode_path, encode_path, normalize_remote_path, resolve_remote_path, write_file_atomically,
+    };
+
+    #[test]
+    fn replaces_files_atomically() {
+        let tree = TempTree::new("atomic");
+        let path = tree.path("index");
+        std::fs::write(&path, "old content which is longer").unwrap();
+
+        write_file_atomically(&path, b"new content").unwrap();
+        let content = std::fs::read_to_string(&path);
+        let entries: Vec<_> = std::fs::read_dir(&tree.root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+
+        assert_eq!(content.unwrap(), "new content");
+        assert_eq!(entries, vec!["index"], "no temporary file is left behind");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serializes_roots_which_are_not_utf8() {
+        use serde::{Deserialize, Serialize};
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        #[derive(Serialize, Deserialize)]
+        struct Request {
+            #[serde(with = "super::encoded_path")]
+            root: PathBuf,
+        }
+        let serialize = |root: &Path| {
+            bincode::serialize(&Request {
+                root: root.to_path_buf(),
+            })
+            .unwrap()
+        };
+        let latin1_root = Path::new(OsStr::from_bytes(b"/home/caf\xe9"));
+
+        let latin1_request = serialize(latin1_root);
+        let decoded: Request = bincode::deserialize(&latin1_request).unwrap();
+
+        assert_eq!(decoded.root, latin1_root);
+        assert_eq!(
+            serialize(Path::new("/home/cafe")),
+            bincode::serialize(&PathBuf::from("/home/cafe")).unwrap(),
+            "valid paths are serialized as before"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn encodes_paths_starting_like_encoded_ones() {
+        for path in ["./x%41", "./link.so", "folder/100%.txt"] {
+            let encoded = encode_path(Path::new(path));
+            assert_eq!(decode_path(&encoded), PathBuf::from(path));
+        }
+        assert_eq!(encode_path(Path::new("./x%41")), "././x%2541");
+        assert_eq!(encode_path(Path::new("folder/100%.txt")), "folder/100%.txt");
+    }
+
+    #[test]
+    fn normalizes_remote_paths() {
+        assert_eq!(
+            normalize_remote_path("./folder//file.txt").unwrap(),
+            PathBuf::from("folder/file.txt")
+        );
+        assert!(normalize_remote_path("").is_err());
+        assert!(normalize_remote_path(".").is_err());
+    }
+
+    #[test]
+    fn refuses_remote_paths_outside_of_the_root() {
+        let root = Path::new("/backup");
+        let no_links = HashSet::new();
+        assert!(resolve_remote_path(root, "../../.bashrc", &no_links).is_err());
+        assert!(resolve_remote_path(root, "folder/../../file.txt", &no_links).is_err());
+        assert!(resolve_remote_path(root, "folder/..", &no_links).is_err());
+        assert!(resolve_remote_path(root, "/etc/passwd", &no_links).is_err());
+        assert_eq!(
+            resolve_remote_path(root, "folder/file.txt", &no_links).unwrap(),
+            PathBuf::from("/backup/folder/file.txt")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_remote_paths_through_symlinked_folders() {
+        let tree = TempTree::new("remote-paths");
+        let outside = TempTree::new("remote-paths-outside");
+        let docs = TempTree::new("remote-paths-docs");
+        tree.create_dir("folder");
+        docs.create_dir("nested");
+        std::os::unix::fs::symlink(&outside.root, tree.path("outside")).unwrap();
+        std::os::unix::fs::symlink(&docs.root, tree.path("docs")).unwrap();
+        std::os::unix::fs::symlink(&outside.root, docs.path("nested/outside")).unwrap();
+        let followed_links = HashSet::from([PathBuf::from("docs")]);
+
+        let through_link = resolve_remote_path(&tree.root, "outside/file.txt", &followed_links);
+        let through_followed_link =
+            resolve_remote_path(&tree.root, "docs/nested/file.txt", &followed_links);
+        let through_link_in_followed_link =
+            resolve_remote_path(&tree.root, "docs/nested/outside/file.txt", &followed_links);
+        let the_link = resolve_remote_path(&tree.root, "outside", &followed_links);
+        let inside = resolve_remote_path(&tree.root, "folder/new/file.txt", &followed_links);
+
+        assert!(through_link.is_err());
+        assert_eq!(
+            through_followed_link.unwrap(),
+            tree.path("docs/nested/file.txt")
+        );
+        assert!(through_link_in_followed_link.is_err());
+        // the link itself is replaced rather than written through
+        assert_eq!(the_link.unwrap(), tree.path("outside"));
+        assert_eq!(inside.unwrap(), tree.path("folder/new/file.txt"));
+    }
+}
@@ -11,38 +11,45 @@ use std::{
 };
 
 use crate::{
-    constants::TCP_FILE_CHUNK_SIZE,
+    constants::{HASH_BUFFER_SIZE, TCP_FILE_CHUNK_SIZE},
     model::{
         api,
+        digest::{ContentHasher, HashAlgorithm},
         tcp::{
             AbortMessage, CheckFileMessage, CommitMessage, DownloadChunkMessage, FileUploadMessage,
             FinishDownloadMessage, TcpMessage, TcpOperation,
         },
-        Result,
+        RedstoneError, Result,
     },
+    util::decode_path,
 };
 
 pub async fn send_message(stream: &mut BufReader<TcpStream>, packet: &[u8]) -> Result<()> {
     let packet_size = get_message_size_in_bytes(packet);
-    Ok(stream.write_all(&[&packet_size, packet].concat()).await?)
+    stream
+        .write_all(&[&packet_size, packet].concat())
+        .await
+        .map_err(connection_lost)
 }
 
 pub async fn receive_message<T: DeserializeOwned>(stream: &mut BufReader<TcpStream>) -> Result<T> {
     let mut incoming_packet_buf: [u8; 4] = [0; 4];
-    stream.read_exact(&mut incoming_packet_buf).await?;
+    stream
+        .read_exact(&mut incoming_packet_buf)
+        .await
+        .map_err(connection_lost)?;
     let incoming_packet_size = u32::from_be_bytes(incoming_packet_buf);
     let mut buffer = vec![0; incoming_packet_size as usize];
-    stream.read_exact(&mut buffer).await?;
+    stream
+        .read_exact(&mut buffer)
+        .await
+        .map_err(connection_lost)?;
     Ok(bson::from_slice(&buffer)?)
 }
 
-pub async fn receive_raw_message(stream: &mut BufReader<TcpStream>) -> Result<Vec<u8>> {
-    let mut incoming_packet_buf: [u8; 4] = [0; 4];
-    stream.read_exact(&mut incoming_packet_buf).await?;
-    let incoming_packet_size = u32::from_be_bytes(incoming_packet_buf);
-    let mut buffer = vec![0; incoming_packet_size as usize];
-    stream.read_exact(&mut buffer).await?;
-    Ok(buffer)
+/// Socket errors are told apart from local ones, so only they retry over a new connection.
+fn connection_lost(error: std::io::Error) -> RedstoneError {
+    RedstoneError::ConnectionLost(error.to_string())
 }
 
 fn get_message_size_in_bytes(message: &[u8]) -> [u8; 4] {
@@ -78,14 +85,22 @@ pub struct FileUploadMessageFactory {
     file_size: usize,
     read_bytes: usize,
     pub last_chunk_size: usize,
+    pub last_chunk_offset: usize,
     times_sent: usize,
+    /// Hashes the bytes as they're sent, so they can be checked against the scanned digest.
+    hasher: ContentHasher,
 }
 
 impl FileUploadMessageFactory {
-    pub fn new(upload_token: &String, file: &api::File, root_folder: PathBuf) -> Self {
-        let file_path = root_folder.join(file.path.clone());
-        let file_size = std::fs::metadata(&file_path).unwrap().len();
-        Self {
+    pub fn new(
+        upload_token: &String,
+        file: &api::File,
+        root_folder: PathBuf,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Self> {
+        let file_path = root_folder.join(decode_path(&file.path));
+        let file_size = std::fs::metadata(&file_path)?.len();
+        Ok(Self {
             upload_token: upload_token.to_owned(),
             file_id: file.id.to_string(),
             file_path,
@@ -93,8 +108,33 @@ impl FileUploadMessageFactory {
             file_size: file_size as usize,
             read_bytes: 0,
             last_chunk_size: 0,
+            last_chunk_offset: 0,
             times_sent: 0,
+            hasher: hash_algorithm.hasher(),
+        })
+    }
+
+    /// Skips the bytes the server already holds, which are still hashed so the digest
+    /// covers the whole file. Offsets past the end of the file start it over.
+    pub fn resume_from(&mut self, offset: usize) -> Result<()> {
+        let offset = if offset <= self.file_size { offset } else { 0 };
+        let mut file = File::open(&self.file_path)?;
+        let mut buffer = vec![0; HASH_BUFFER_SIZE];
+        let mut hashed_bytes = 0;
+        while hashed_bytes < offset {
+            let size = usize::min(offset - hashed_bytes, HASH_BUFFER_SIZE);
+            file.read_exact(&mut buffer[..size])?;
+            self.hasher.update(&buffer[..size]);
+            hashed_bytes += size;
         }
+        self.read_bytes = offset;
+        self.last_chunk_offset = offset;
+        Ok(())
+    }
+
+    /// Digest of the bytes which were sent.
+    pub fn digest(self) -> String {
+        self.hasher.finalize()
     }
 
     pub fn has_data_to_fetch(&self) -> bool {
@@ -108,10 +148,12 @@ impl FileUploadMessageFactory {
     fn get_next_chunk(&mut self) -> Result<Vec<u8>> {
         let chunk_size = usize::min(self.remaining_bytes_to_read(), TCP_FILE_CHUNK_SIZE);
         self.last_chunk_size = chunk_size;
+        self.last_chunk_offset = self.read_bytes;
         let mut file = File::open(&self.file_path)?;
         file.seek(SeekFrom::Start(self.read_bytes as u64))?;
         let mut buffer: Vec<u8> = vec![0; chunk_size];
         file.read_exact(&mut buffer)?;
+        self.hasher.update(&buffer);
         self.chunk_offset += 1;
         self.times_sent += 1;
         self.read_bytes += chunk_size;
@@ -129,6 +171,7 @@ impl TcpMessage for FileUploadMessageFactory {
             operation: TcpOperation::UploadChunk,
             file_id: self.file_id.to_string(),
             file_size: self.file_size,
+            offset: self.last_chunk_offset,
             data,
             last_chunk: !self.has_data_to_fetch(),
         };
@@ -189,15 +232,16 @@ impl TcpMessage for CheckFileMessageFactory {
 pub struct DownloadChunkMessageFactory {
     pub download_token: String,
     pub file_id: String,
-    pub offset: usize,
+    /// Index of the next chunk, counted in `TCP_FILE_CHUNK_SIZE` chunks.
+    pub chunk_index: usize,
 }
 
 impl DownloadChunkMessageFactory {
-    pub fn new(download_token: String, file_id: String) -> Self {
+    pub fn new(download_token: String, file_id: String, chunk_index: usize) -> Self {
         Self {
             download_token,
             file_id,
-            offset: 0,
+            chunk_index,
         }
     }
 }
@@ -210,9 +254,9 @@ impl TcpMessage for DownloadChunkMessageFactory {
             download_token: self.download_token.to_string(),
             file_id: self.file_id.to_string(),
             byte_limit: TCP_FILE_CHUNK_SIZE,
-            offset: self.offset,
+            chunk_index: self.chunk_index,
         };
-        self.offset += 1;
+        self.chunk_index += 1;
         Ok(bson::to_vec(&message)?)
     }
 }
@@ -237,3 +281,96 @@ impl TcpMessage for FinishDownloadMessageFactory {
         Ok(bson::to_vec(&message)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        constants::TCP_FILE_CHUNK_SIZE,
+        model::{
+            api::{File, FileOperation, FileUpdate},
+            fs_tree::FileKind,
+            tcp::{DownloadChunkMessage, FileUploadMessage, TcpMessage},
+        },
+        testing::TempTree,
+        util::generate_digest_from_bytes,
+    };
+
+    use super::{DownloadChunkMessageFactory, FileUploadMessageFactory};
+
+    #[test]
+    fn resumed_uploads_hash_the_whole_file() {
+        let tree = TempTree::new("resume");
+        let content: Vec<u8> = (0..TCP_FILE_CHUNK_SIZE * 2 + 123)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        tree.write("file.bin", &content);
+        let file = File {
+            id: "file".into(),
+            path: "file.bin".into(),
+            digest: String::new(),
+            hash_algorithm: Default::default(),
+            last_update: FileUpdate {
+                operation: FileOperation::Add,
+            },
+            metadata: None,
+            kind: FileKind::File,
+            from_path: None,
+        };
+        let size = content.len();
+
+        let resumed: Vec<_> = [
+            0,
+            TCP_FILE_CHUNK_SIZE,
+            TCP_FILE_CHUNK_SIZE + 7,
+            size,
+            size + 1,
+        ]
+        .into_iter()
+        .map(|offset| {
+            let mut factory = FileUploadMessageFactory::new(
+                &"token".into(),
+                &file,
+                tree.root.clone(),
+                Default::default(),
+            )
+            .unwrap();
+            factory.resume_from(offset).unwrap();
+            let mut sent_from = None;
+            while factory.has_data_to_fetch() {
+                let payload = factory.get_tcp_payload().unwrap();
+                let message: FileUploadMessage = bson::from_slice(&payload).unwrap();
+                sent_from.get_or_insert(message.offset);
+            }
+            (offset, sent_from, factory.digest())
+        })
+        .collect();
+
+        let digest = generate_digest_from_bytes(&content, Default::default());
+        for (offset, sent_from, resumed_digest) in resumed {
+            assert_eq!(resumed_digest, digest, "resumed from {offset}");
+            let expected = match offset {
+                offset if offset == size => None,
+                // offsets past the end start the file over
+                offset if offset > size => Some(0),
+                offset => Some(offset),
+            };
+            assert_eq!(sent_from, expected, "resumed from {offset}");
+        }
+    }
+
+    #[test]
+    fn requests_download_chunks_by_index_from_the_resumed_one() {
+        let mut factory = DownloadChunkMessageFactory::new("token".into(), "file".into(), 3);
+
+        let chunk_indexes: Vec<usize> = (0..2)
+            .map(|_| {
+                let payload = factory.get_tcp_payload().unwrap();
+                bson::from_slice::<DownloadChunkMessage>(&payload)
+                    .unwrap()
+                    .chunk_index
+            })
+            .collect();
+
+        assert_eq!(chunk_indexes, vec![3, 4]);
+    }
+}
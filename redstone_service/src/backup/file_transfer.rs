@@ -1,21 +1,32 @@
 use std::{
     borrow::BorrowMut,
+    collections::{HashMap, HashSet, VecDeque},
+    io::{Read, SeekFrom},
     path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
 };
 
-use async_recursion::async_recursion;
+use futures_util::future::try_join_all;
+
 use redstone_common::{
-    constants::TCP_FILE_CHUNK_SIZE,
+    constants::{HASH_BUFFER_SIZE, TCP_FILE_CHUNK_SIZE},
     model::{
         api::{File as RSFile, FileOperation},
+        digest::{ContentHasher, HashAlgorithm},
+        fs_tree::{FSTree, FileKind, RSFile as LocalFile, SymlinkPolicy},
         ipc::{FileAction, FileActionProgress},
-        tcp::{TcpMessage, TcpMessageResponse, TcpMessageResponseStatus},
-        RedstoneError, Result,
+        tcp::{
+            DownloadChunk, FileCheck, TcpMessage, TcpMessageResponse, TcpMessageResponseStatus,
+            TransferOptions, UploadChunkAck,
+        },
+        DomainError, RedstoneError, Result,
     },
+    util::{decode_path, generate_digest, normalize_remote_path, resolve_remote_path},
     web::{
         api::get_tcp_base_url,
         tcp::{
-            receive_message, receive_raw_message, send_message, CheckFileMessageFactory,
+            receive_message, send_message, AbortUpdateMessageFactory, CheckFileMessageFactory,
             CommitMessageFactory, DownloadChunkMessageFactory, FileUploadMessageFactory,
             FinishDownloadMessageFactory,
         },
@@ -23,79 +34,249 @@ use redstone_common::{
 };
 
 use tokio::{
-    io::{AsyncWriteExt, BufReader},
+    io::{AsyncSeekExt, AsyncWriteExt, BufReader},
     net::TcpStream,
     sync::mpsc::UnboundedSender,
+    task::spawn_blocking,
 };
 
+/// Zero filled blocks of this size are written back as holes.
+const SPARSE_BLOCK_SIZE: usize = 1024 * 64; // 64KB
+
+/// Times a file's upload continues over a new connection after losing the previous one.
+const MAX_RECONNECTS: u8 = 3;
+
+/// What every file of an upload is sent with.
+struct Upload<'a> {
+    upload_token: &'a String,
+    fs_tree: &'a FSTree,
+    options: TransferOptions,
+    progress: &'a SharedProgress,
+    /// Whether the server may already hold part of the files, from an interrupted push.
+    resume: bool,
+}
+
+/// Progress of every connection of a transfer, reported as a single stream.
+struct SharedProgress {
+    progress: Mutex<FileActionProgress>,
+    emitter: UnboundedSender<FileActionProgress>,
+}
+
+impl SharedProgress {
+    fn new(
+        operation: FileAction,
+        total: u64,
+        emitter: UnboundedSender<FileActionProgress>,
+    ) -> Self {
+        let progress = FileActionProgress {
+            operation,
+            total,
+            ..Default::default()
+        };
+        Self {
+            progress: Mutex::new(progress),
+            emitter,
+        }
+    }
+
+    fn advance(&self, file_name: &str, bytes: u64) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.current_file_name = file_name.to_owned();
+        progress.progress += bytes;
+        let _ = self.emitter.send(progress.clone());
+    }
+
+    /// Counts the bytes of a file up to `end`, leaving out the ones which were
+    /// already counted before a connection was lost.
+    fn advance_file(&self, file_name: &str, counted_bytes: &mut usize, end: usize) {
+        if end > *counted_bytes {
+            self.advance(file_name, (end - *counted_bytes) as u64);
+            *counted_bytes = end;
+        }
+    }
+
+    fn finish(&self) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.progress = progress.total;
+        let _ = self.emitter.send(progress.clone());
+    }
+}
+
+/// Files waiting for a connection to pick them up.
+type TransferQueue<T> = Mutex<VecDeque<T>>;
+
+/// Takes the next file out of the queue, releasing the lock before it's transferred.
+fn next_in_queue<T>(queue: &TransferQueue<T>) -> Option<T> {
+    queue.lock().unwrap().pop_front()
+}
+
+/// Amount of connections needed for the given amount of files, which is at least one
+/// as it's also used to commit the transfer.
+fn count_connections(options: &TransferOptions, files: usize) -> usize {
+    options.connections.min(files).max(1)
+}
+
+async fn connect() -> Result<BufReader<TcpStream>> {
+    let stream = TcpStream::connect(get_tcp_base_url()?.to_string()).await?;
+    Ok(BufReader::new(stream))
+}
+
+/// Uploads the content of the files, continuing whatever the server already holds of
+/// them when `resume` is set.
 pub async fn send_files(
     files: &[RSFile],
     upload_token: &String,
-    root_folder: PathBuf,
+    fs_tree: &FSTree,
     total_size: u64,
+    options: TransferOptions,
+    resume: bool,
     progress_emitter: UnboundedSender<FileActionProgress>,
 ) -> Result<()> {
     println!("{:?}", get_tcp_base_url()?);
-    let stream = TcpStream::connect(get_tcp_base_url()?).await?;
-    let mut stream = BufReader::new(stream);
-    let mut progress = FileActionProgress {
-        operation: FileAction::Upload,
-        total: total_size,
-        ..Default::default()
+    let progress = SharedProgress::new(FileAction::Upload, total_size, progress_emitter);
+    let upload = Upload {
+        upload_token,
+        fs_tree,
+        options,
+        progress: &progress,
+        resume,
     };
-    for file in files
+    let local_files: HashMap<&str, &LocalFile> = fs_tree
+        .files
         .iter()
-        .filter(|file| file.last_update.operation != FileOperation::Remove)
-        .collect::<Vec<&RSFile>>()
-    {
-        send_file(
-            &mut stream,
-            file,
-            upload_token,
-            &root_folder,
-            &mut progress,
-            &progress_emitter,
-        )
-        .await?;
+        .map(|file| (file.path.as_str(), file))
+        .collect();
+    // moved files are relinked by the server, while metadata changes and symlinks
+    // are fully described by the push request, so there's no content to send for them
+    let mut queue = VecDeque::new();
+    for file in files.iter().filter(|file| {
+        !matches!(
+            file.last_update.operation,
+            FileOperation::Remove | FileOperation::Move | FileOperation::UpdateMetadata
+        ) && file.kind == FileKind::File
+    }) {
+        let Some(local_file) = local_files.get(file.path.as_str()) else {
+            return Err(RedstoneError::BaseError(format!(
+                "{} isn't part of the scanned files",
+                file.path
+            )));
+        };
+        queue.push_back((file, *local_file));
     }
-    send_commit_msg(&mut stream, upload_token).await
+    let connections = count_connections(&options, queue.len());
+    let queue = Mutex::new(queue);
+    let workers = (0..connections).map(|_| upload_worker(&queue, &upload));
+    // the upload is only committed once every connection sent all of its files
+    let mut streams = match try_join_all(workers).await {
+        Ok(streams) => streams,
+        Err(err @ RedstoneError::DomainError(DomainError::FileChangedDuringBackup(_))) => {
+            // nothing may be committed once the sent data doesn't match what was declared
+            abort_upload(upload_token).await?;
+            return Err(err);
+        }
+        Err(err) => return Err(err),
+    };
+    send_commit_msg(&mut streams[0], upload_token).await
+}
+
+/// Sends the files of the queue over its own connection until there are none left.
+async fn upload_worker(
+    queue: &TransferQueue<(&RSFile, &LocalFile)>,
+    upload: &Upload<'_>,
+) -> Result<BufReader<TcpStream>> {
+    let mut stream = connect().await?;
+    while let Some((file, local_file)) = next_in_queue(queue) {
+        let mut resume = upload.resume;
+        let mut counted_bytes = 0;
+        let mut reconnects = 0;
+        loop {
+            match send_file(
+                &mut stream,
+                file,
+                local_file,
+                upload,
+                resume,
+                &mut counted_bytes,
+            )
+            .await
+            {
+                // the server keeps what it received, so the file continues from there
+                Err(RedstoneError::ConnectionLost(_)) if reconnects < MAX_RECONNECTS => {
+                    reconnects += 1;
+                    resume = true;
+                    stream = connect().await?;
+                }
+                result => break result?,
+            }
+        }
+    }
+    Ok(stream)
+}
+
+/// Lets the server drop whatever it received with an upload token which won't be committed.
+pub async fn abort_upload(upload_token: &str) -> Result<()> {
+    let mut stream = connect().await?;
+    send_abort_msg(&mut stream, upload_token).await
 }
 
 pub async fn download_files(
-    root: PathBuf,
+    destination: Destination<'_>,
     files: &[RSFile],
     download_token: String,
     total_size: u64,
+    options: TransferOptions,
     progress_emitter: UnboundedSender<FileActionProgress>,
 ) -> Result<()> {
-    let stream = TcpStream::connect(get_tcp_base_url()?.to_string()).await?;
-    let mut stream = BufReader::new(stream);
-    let mut progress = FileActionProgress {
-        total: total_size,
-        operation: FileAction::Download,
-        progress: 0,
-        current_file_name: String::new(),
-    };
+    // nothing is written or deleted when the server sent any path outside of the backup
+    let unsafe_paths = find_unsafe_paths(destination, files);
+    if !unsafe_paths.is_empty() {
+        return Err(RedstoneError::DomainError(DomainError::UnsafeRemotePaths(
+            unsafe_paths,
+        )));
+    }
+    let mut hard_links = Vec::new();
+    let mut queue = VecDeque::new();
     for file in files
         .iter()
         .filter(|file| file.last_update.operation != FileOperation::Remove)
-        .collect::<Vec<&RSFile>>()
     {
-        progress.current_file_name = file.path.to_owned();
-        download_file(
-            &mut stream,
-            file,
-            &root,
-            download_token.clone(),
-            &mut progress,
-            &progress_emitter,
-        )
-        .await?;
-        println!("downloaded {}", file.path);
+        match &file.kind {
+            FileKind::Symlink { target } => {
+                create_symlink(destination, file, target).await?;
+                continue;
+            }
+            FileKind::Directory => {
+                tokio::fs::create_dir_all(destination.local_path(&file.path)?).await?;
+                continue;
+            }
+            FileKind::HardLink { target } => {
+                // links are made once their target has been downloaded
+                hard_links.push((file, target));
+                continue;
+            }
+            FileKind::File => {}
+        }
+        if file.last_update.operation == FileOperation::UpdateMetadata
+            || move_local_file(destination, file).await?
+        {
+            apply_metadata(destination, file).await?;
+            continue;
+        }
+        queue.push_back(file);
     }
+    let connections = count_connections(&options, queue.len());
+    let queue = Mutex::new(queue);
+    let progress = SharedProgress::new(FileAction::Download, total_size, progress_emitter);
+    let workers =
+        (0..connections).map(|_| download_worker(&queue, destination, &download_token, &progress));
+    let mut streams = try_join_all(workers).await?;
+    for (file, target) in hard_links {
+        create_hard_link(destination, file, target).await?;
+    }
+    let stream = &mut streams[0];
     let packet = FinishDownloadMessageFactory::new(download_token.to_string()).get_tcp_payload()?;
-    send_message(&mut stream, &packet).await?;
-    let response: TcpMessageResponse<Vec<u8>> = receive_message(&mut stream).await?;
+    send_message(stream, &packet).await?;
+    let response: TcpMessageResponse<Vec<u8>> = receive_message(stream).await?;
     if response.status != TcpMessageResponseStatus::Ok {
         let error = format!(
             "Error commiting finalizing download.\nServer responded: {}",
@@ -103,47 +284,113 @@ pub async fn download_files(
         );
         return Err(RedstoneError::BaseError(error));
     }
-    delete_removed_files(&root, files).await?;
-    progress.progress = progress.total;
-    let _ = progress_emitter.send(progress);
+    delete_removed_files(destination, files).await?;
+    // leftovers of downloads which were interrupted for good are dropped as well
+    match tokio::fs::remove_dir_all(get_downloads_folder_for_path(destination.root)).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+        _ => {}
+    }
+    progress.finish();
     Ok(())
 }
 
-async fn send_file<'a>(
+/// Downloads the files of the queue over its own connection until there are none left.
+async fn download_worker(
+    queue: &TransferQueue<&RSFile>,
+    destination: Destination<'_>,
+    download_token: &str,
+    progress: &SharedProgress,
+) -> Result<BufReader<TcpStream>> {
+    let mut stream = connect().await?;
+    while let Some(file) = next_in_queue(queue) {
+        download_file(
+            &mut stream,
+            file,
+            destination,
+            download_token.to_owned(),
+            progress,
+        )
+        .await?;
+    }
+    Ok(stream)
+}
+
+/// Uploads a file, starting from the bytes the server already holds when resuming.
+/// `counted_bytes` is how much of it was already reported as progress.
+async fn send_file(
     stream: &mut BufReader<TcpStream>,
     file: &RSFile,
-    upload_token: &String,
-    root_folder: &Path,
-    file_action_progress: &'a mut FileActionProgress,
-    progress_emitter: &'a UnboundedSender<FileActionProgress>,
+    local_file: &LocalFile,
+    upload: &Upload<'_>,
+    resume: bool,
+    counted_bytes: &mut usize,
 ) -> Result<()> {
     println!("Uploading {} file", file.path);
-    file_action_progress.current_file_name = file.path.to_owned();
+    let Upload {
+        upload_token,
+        fs_tree,
+        options,
+        progress,
+        ..
+    } = upload;
+    // the file was scanned from this path, which is only checked when it comes from the server
+    let path = fs_tree.root.join(decode_path(&file.path));
+    let changed_error =
+        || RedstoneError::DomainError(DomainError::FileChangedDuringBackup(file.path.to_owned()));
 
+    let mut offset = match resume {
+        true => get_received_bytes(stream, upload_token, file).await?,
+        false => 0,
+    };
     let mut retry_count: u8 = 0;
     loop {
-        let mut file_upload_message =
-            FileUploadMessageFactory::new(upload_token, file, root_folder.to_path_buf());
-        while file_upload_message.has_data_to_fetch() {
-            let packet = file_upload_message.get_tcp_payload()?;
-            send_message(stream.borrow_mut(), &packet).await?;
-
-            file_action_progress.progress += file_upload_message.last_chunk_size as u64;
-            let _ = progress_emitter.send(file_action_progress.clone());
-
-            let response: TcpMessageResponse<()> = receive_message(stream.borrow_mut()).await?;
-            if response.status != TcpMessageResponseStatus::Ok {
-                let error = format!(
-                    "Error commiting backup transaction.\nServer responded: {}",
-                    response.reason.unwrap()
-                );
-                return Err(RedstoneError::BaseError(error));
+        if !is_unchanged_since_scan(&path, local_file)? {
+            return Err(changed_error());
+        }
+        let mut file_upload_message = FileUploadMessageFactory::new(
+            upload_token,
+            file,
+            fs_tree.root.clone(),
+            fs_tree.hash_algorithm,
+        )?;
+        file_upload_message.resume_from(offset)?;
+        progress.advance_file(&file.path, counted_bytes, offset);
+        // the server asks for a retry when the file it got doesn't check out
+        offset = 0;
+        // offsets and sizes of the chunks sent but not acknowledged yet
+        let mut chunks_in_flight: VecDeque<(usize, usize)> = VecDeque::new();
+        while file_upload_message.has_data_to_fetch() || !chunks_in_flight.is_empty() {
+            while chunks_in_flight.len() < options.upload_window.max(1)
+                && file_upload_message.has_data_to_fetch()
+            {
+                let packet = match file_upload_message.get_tcp_payload() {
+                    Ok(packet) => packet,
+                    // reading fails when the file is truncated halfway through the upload
+                    Err(_) if !is_unchanged_since_scan(&path, local_file)? => {
+                        return Err(changed_error())
+                    }
+                    Err(err) => return Err(err),
+                };
+                send_message(stream.borrow_mut(), &packet).await?;
+                chunks_in_flight.push_back((
+                    file_upload_message.last_chunk_offset,
+                    file_upload_message.last_chunk_size,
+                ));
             }
+
+            let response: TcpMessageResponse<UploadChunkAck> =
+                receive_message(stream.borrow_mut()).await?;
+            let (chunk_offset, chunk_size) =
+                acknowledge_chunk(file, &mut chunks_in_flight, response)?;
+            progress.advance_file(&file.path, counted_bytes, chunk_offset + chunk_size);
+        }
+        if file_upload_message.digest() != local_file.digest {
+            return Err(changed_error());
         }
         let check_file_message =
             CheckFileMessageFactory::new(upload_token, &file.id).get_tcp_payload()?;
         send_message(stream.borrow_mut(), &check_file_message).await?;
-        let response: TcpMessageResponse<()> = receive_message(stream.borrow_mut()).await?;
+        let response: TcpMessageResponse<FileCheck> = receive_message(stream.borrow_mut()).await?;
         match response.status {
             TcpMessageResponseStatus::Error => {
                 return Err(RedstoneError::BaseError(format!(
@@ -169,6 +416,55 @@ async fn send_file<'a>(
     Ok(())
 }
 
+/// Removes the chunk a response refers to from the ones in flight, returning its offset and size.
+///
+/// Responses without acknowledgement data refer to the oldest chunk, as servers
+/// which don't send it handle chunks in order.
+fn acknowledge_chunk(
+    file: &RSFile,
+    chunks_in_flight: &mut VecDeque<(usize, usize)>,
+    response: TcpMessageResponse<UploadChunkAck>,
+) -> Result<(usize, usize)> {
+    let idx = match &response.data {
+        Some(ack) if ack.file_id == file.id => chunks_in_flight
+            .iter()
+            .position(|(offset, _)| *offset == ack.offset),
+        Some(_) => None,
+        None => Some(0).filter(|_| !chunks_in_flight.is_empty()),
+    };
+    let Some((offset, size)) = idx.and_then(|idx| chunks_in_flight.remove(idx)) else {
+        return Err(RedstoneError::BaseError(format!(
+            "Server acknowledged a chunk which wasn't sent: {:?}",
+            response.data
+        )));
+    };
+    if response.status != TcpMessageResponseStatus::Ok {
+        let error = format!(
+            "Error uploading {} at offset {offset}.\nServer responded: {}",
+            file.path,
+            response.reason.unwrap_or_default()
+        );
+        return Err(RedstoneError::BaseError(error));
+    }
+    Ok((offset, size))
+}
+
+/// Asks how many bytes of a file the server already holds under the upload token.
+async fn get_received_bytes(
+    stream: &mut BufReader<TcpStream>,
+    upload_token: &String,
+    file: &RSFile,
+) -> Result<usize> {
+    let packet = CheckFileMessageFactory::new(upload_token, &file.id).get_tcp_payload()?;
+    send_message(stream, &packet).await?;
+    let response: TcpMessageResponse<FileCheck> = receive_message(stream).await?;
+    // servers which don't keep partial files have nothing to resume from
+    Ok(match (response.status, response.data) {
+        (TcpMessageResponseStatus::Ok, Some(check)) => check.received_bytes,
+        _ => 0,
+    })
+}
+
 async fn send_commit_msg(stream: &mut BufReader<TcpStream>, upload_token: &str) -> Result<()> {
     let commit_payload = CommitMessageFactory::new(upload_token.to_owned()).get_tcp_payload()?;
     println!("Sending commit msg!");
@@ -184,78 +480,740 @@ async fn send_commit_msg(stream: &mut BufReader<TcpStream>, upload_token: &str)
     Ok(())
 }
 
-async fn download_file<'a>(
+async fn send_abort_msg(stream: &mut BufReader<TcpStream>, upload_token: &str) -> Result<()> {
+    let abort_payload =
+        AbortUpdateMessageFactory::new(upload_token.to_owned()).get_tcp_payload()?;
+    send_message(stream.borrow_mut(), &abort_payload).await?;
+    let _: TcpMessageResponse<()> = receive_message(stream.borrow_mut()).await?;
+    Ok(())
+}
+
+/// Whether the file still has the size and modification time it had when it was scanned.
+fn is_unchanged_since_scan(path: &Path, local_file: &LocalFile) -> Result<bool> {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(metadata.len() == local_file.size
+        && mtime.as_secs() as i64 == local_file.metadata.mtime
+        && mtime.subsec_nanos() == local_file.metadata.mtime_nsec)
+}
+
+/// Downloads a file next to the index and only replaces the local one once its
+/// content matches the digest the server declared.
+async fn download_file(
     stream: &mut BufReader<TcpStream>,
     file: &RSFile,
-    root: &Path,
+    destination: Destination<'_>,
     download_token: String,
-    file_action_progress: &'a mut FileActionProgress,
-    progress_emitter: &'a UnboundedSender<FileActionProgress>,
+    progress: &SharedProgress,
 ) -> Result<()> {
-    file_action_progress.current_file_name = file.path.to_owned();
-    let mut path = root.to_path_buf();
-    path.push(file.path.clone());
-    if path.is_file() {
-        tokio::fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&path)
-            .await?;
-    } else if let Some(prefix) = path.parent() {
+    let path = destination.local_path(&file.path)?;
+    let temp_path = get_temp_download_path(destination.root, file);
+    let mut download = Download {
+        stream,
+        file,
+        temp_path: &temp_path,
+        download_token: &download_token,
+        progress,
+        counted_bytes: 0,
+    };
+    let (mut digest, resumed_from) = receive_file(&mut download, true).await?;
+    if digest != file.digest && resumed_from > 0 {
+        // what an interrupted download left behind may not have been written properly
+        (digest, _) = receive_file(&mut download, false).await?;
+    }
+    if digest != file.digest {
+        tokio::fs::remove_file(&temp_path).await?;
+        return Err(RedstoneError::DomainError(
+            DomainError::DownloadedFileMismatch(file.path.to_owned()),
+        ));
+    }
+    if let Some(prefix) = path.parent() {
         tokio::fs::create_dir_all(prefix).await?;
     }
-    let mut factory = DownloadChunkMessageFactory::new(download_token.clone(), file.id.clone());
+    // renaming replaces a symlink, or a hard link of the old file,
+    // instead of overwriting whatever else it points to
+    tokio::fs::rename(&temp_path, &path).await?;
+    apply_metadata(destination, file).await?;
+    println!("downloaded {}", file.path);
+    Ok(())
+}
+
+/// A file being downloaded into its temporary file.
+struct Download<'a> {
+    stream: &'a mut BufReader<TcpStream>,
+    file: &'a RSFile,
+    temp_path: &'a Path,
+    download_token: &'a str,
+    progress: &'a SharedProgress,
+    /// How much of the file was already reported as progress.
+    counted_bytes: usize,
+}
+
+/// Downloads a file into its temporary file, continuing from whatever a previous
+/// download left in there when `resume` is set.
+/// Returns the digest of the whole file and the offset the download started from.
+async fn receive_file(download: &mut Download<'_>, resume: bool) -> Result<(String, usize)> {
+    let file = download.file;
+    if let Some(prefix) = download.temp_path.parent() {
+        tokio::fs::create_dir_all(prefix).await?;
+    }
+    let (mut hasher, offset) = match resume {
+        true => {
+            let (temp_path, algorithm) = (download.temp_path.to_path_buf(), file.hash_algorithm);
+            spawn_blocking(move || hash_partial_download(&temp_path, algorithm)).await??
+        }
+        false => (file.hash_algorithm.hasher(), 0),
+    };
+    let mut temp_file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(download.temp_path)
+        .await?;
+    temp_file.set_len(offset as u64).await?;
+    temp_file.seek(SeekFrom::Start(offset as u64)).await?;
+    download
+        .progress
+        .advance_file(&file.path, &mut download.counted_bytes, offset);
+
+    let mut written_bytes = offset;
+    let mut factory = DownloadChunkMessageFactory::new(
+        download.download_token.to_owned(),
+        file.id.clone(),
+        offset / TCP_FILE_CHUNK_SIZE,
+    );
     loop {
         let packet = factory.get_tcp_payload()?;
-        send_message(stream.borrow_mut(), &packet).await?;
-        let data: Vec<u8> = receive_raw_message(stream.borrow_mut()).await?;
-        let mut file = tokio::fs::OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(&path)
-            .await?;
-        file.write_all(&data).await?;
-        file_action_progress.progress += data.len() as u64;
-        let _ = progress_emitter.send(file_action_progress.clone());
-        if data.len() < TCP_FILE_CHUNK_SIZE {
+        send_message(download.stream, &packet).await?;
+        let response: TcpMessageResponse<DownloadChunk> = receive_message(download.stream).await?;
+        let chunk = read_download_chunk(file, written_bytes, response)?;
+        hasher.update(&chunk.data);
+        write_sparse(&mut temp_file, &chunk.data).await?;
+        written_bytes += chunk.data.len();
+        download
+            .progress
+            .advance_file(&file.path, &mut download.counted_bytes, written_bytes);
+        if is_last_chunk(&chunk, written_bytes) {
             break;
         }
     }
-    println!("downloaded {}", file.path);
+    // a trailing hole is only part of the file once its length is set
+    temp_file.set_len(written_bytes as u64).await?;
+    temp_file.sync_all().await?;
+    Ok((hasher.finalize(), offset))
+}
+
+/// Checks that a response holds the chunk of the file which was requested.
+fn read_download_chunk(
+    file: &RSFile,
+    offset: usize,
+    response: TcpMessageResponse<DownloadChunk>,
+) -> Result<DownloadChunk> {
+    if response.status != TcpMessageResponseStatus::Ok {
+        let error = format!(
+            "Error downloading {} at offset {offset}.\nServer responded: {}",
+            file.path,
+            response.reason.unwrap_or_default()
+        );
+        return Err(RedstoneError::BaseError(error));
+    }
+    match response.data {
+        Some(chunk)
+            if chunk.file_id == file.id
+                && chunk.offset == offset
+                && (chunk.last_chunk || !chunk.data.is_empty() || offset >= chunk.total_size) =>
+        {
+            Ok(chunk)
+        }
+        // anything else would be written at the wrong place, or never end
+        chunk => Err(RedstoneError::BaseError(format!(
+            "Server sent a chunk of {} which wasn't requested at offset {offset}: {:?}",
+            file.path,
+            chunk.map(|chunk| (chunk.file_id, chunk.offset, chunk.data.len()))
+        ))),
+    }
+}
+
+/// Whether a file is over once a chunk was written, which servers not flagging
+/// the last chunk tell by its size.
+fn is_last_chunk(chunk: &DownloadChunk, written_bytes: usize) -> bool {
+    chunk.last_chunk || written_bytes >= chunk.total_size
+}
+
+/// Hashes what an interrupted download left in a temporary file, returning the hasher
+/// along with the length it can be continued from.
+fn hash_partial_download(path: &Path, algorithm: HashAlgorithm) -> Result<(ContentHasher, usize)> {
+    let mut hasher = algorithm.hasher();
+    let mut file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok((hasher, 0)),
+        Err(err) => return Err(err.into()),
+    };
+    let length = get_resumable_length(file.metadata()?.len() as usize);
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    let mut hashed_bytes = 0;
+    while hashed_bytes < length {
+        let size = usize::min(length - hashed_bytes, HASH_BUFFER_SIZE);
+        file.read_exact(&mut buffer[..size])?;
+        hasher.update(&buffer[..size]);
+        hashed_bytes += size;
+    }
+    Ok((hasher, length))
+}
+
+/// Cuts a partial download back to its whole chunks, as downloads are requested by
+/// chunk index rather than by byte.
+fn get_resumable_length(partial_length: usize) -> usize {
+    partial_length - partial_length % TCP_FILE_CHUNK_SIZE
+}
+
+/// Folder where files are downloaded to before they replace the local ones, which is
+/// kept until the download finishes so an interrupted one can be continued.
+fn get_downloads_folder_for_path(root: &Path) -> PathBuf {
+    let mut path = root.to_path_buf();
+    path.push(".rs");
+    path.push("downloads");
+    path
+}
+
+fn get_temp_download_path(root: &Path, file: &RSFile) -> PathBuf {
+    // ids come from the server, so they're encoded instead of being trusted as file names
+    let file_name: String = file.id.bytes().map(|byte| format!("{byte:02x}")).collect();
+    get_downloads_folder_for_path(root).join(file_name)
+}
+
+/// Writes a chunk, seeking over the blocks which only hold zeros so they're left as holes.
+async fn write_sparse(file: &mut tokio::fs::File, data: &[u8]) -> Result<()> {
+    for block in data.chunks(SPARSE_BLOCK_SIZE) {
+        if block.len() == SPARSE_BLOCK_SIZE && block.iter().all(|byte| *byte == 0) {
+            file.seek(SeekFrom::Current(SPARSE_BLOCK_SIZE as i64))
+                .await?;
+        } else {
+            file.write_all(block).await?;
+        }
+    }
     Ok(())
 }
 
-async fn delete_removed_files(root: &Path, files: &[RSFile]) -> Result<()> {
-    for file in files
+async fn create_hard_link(destination: Destination<'_>, file: &RSFile, target: &str) -> Result<()> {
+    let path = destination.local_path(&file.path)?;
+    let target = destination.local_path(target)?;
+    if tokio::fs::symlink_metadata(&path).await.is_ok() {
+        tokio::fs::remove_file(&path).await?;
+    } else if let Some(prefix) = path.parent() {
+        tokio::fs::create_dir_all(prefix).await?;
+    }
+    tokio::fs::hard_link(target, &path).await?;
+    Ok(())
+}
+
+async fn create_symlink(destination: Destination<'_>, file: &RSFile, target: &str) -> Result<()> {
+    let path = destination.local_path(&file.path)?;
+    let target = decode_path(target);
+    if tokio::fs::symlink_metadata(&path).await.is_ok() {
+        tokio::fs::remove_file(&path).await?;
+    } else if let Some(prefix) = path.parent() {
+        tokio::fs::create_dir_all(prefix).await?;
+    }
+
+    #[cfg(unix)]
+    tokio::fs::symlink(target, &path).await?;
+    #[cfg(windows)]
+    tokio::fs::symlink_file(target, &path).await?;
+    Ok(())
+}
+
+/// Moves the local copy of a moved file to its new path, as long as it still has the
+/// content which was moved. Returns whether it was moved, so it doesn't need downloading.
+async fn move_local_file(destination: Destination<'_>, file: &RSFile) -> Result<bool> {
+    let Some(from_path) = file
+        .from_path
+        .as_ref()
+        .filter(|_| file.last_update.operation == FileOperation::Move)
+    else {
+        return Ok(false);
+    };
+    let from_path = destination.local_path(from_path)?;
+    let is_file = tokio::fs::symlink_metadata(&from_path)
+        .await
+        .is_ok_and(|metadata| metadata.is_file());
+    let is_same_file = is_file && {
+        let (from_path, algorithm) = (from_path.clone(), file.hash_algorithm);
+        spawn_blocking(move || generate_digest(&from_path, algorithm)).await?? == file.digest
+    };
+    if !is_same_file {
+        return Ok(false);
+    }
+    let path = destination.local_path(&file.path)?;
+    if let Some(prefix) = path.parent() {
+        tokio::fs::create_dir_all(prefix).await?;
+    }
+    tokio::fs::rename(&from_path, &path).await?;
+    Ok(true)
+}
+
+/// Backup folder which downloaded files are written into.
+#[derive(Clone, Copy)]
+pub struct Destination<'a> {
+    root: &'a Path,
+    /// Whether the backup follows symlinks, so its files may be found through symlinked folders.
+    follow_symlinks: bool,
+    /// Symlinked folders the last scan found files through, which may be written into.
+    followed_links: &'a HashSet<PathBuf>,
+}
+
+impl<'a> Destination<'a> {
+    pub fn new(
+        root: &'a Path,
+        symlinks: SymlinkPolicy,
+        followed_links: &'a HashSet<PathBuf>,
+    ) -> Self {
+        Self {
+            root,
+            follow_symlinks: symlinks == SymlinkPolicy::Follow,
+            followed_links,
+        }
+    }
+
+    /// Where a path received from the server belongs, refusing the ones outside of the backup.
+    fn local_path(&self, path: &str) -> Result<PathBuf> {
+        resolve_remote_path(self.root, path, self.followed_links)
+    }
+}
+
+/// Lists the files whose path, or link target, could reach outside of the backup,
+/// including through symlinked folders which exist already or are created by the same
+/// update, so nothing is written before every path was checked.
+fn find_unsafe_paths(destination: Destination<'_>, files: &[RSFile]) -> Vec<String> {
+    let created_symlinks: HashSet<PathBuf> = files
+        .iter()
+        .filter(|file| {
+            matches!(file.kind, FileKind::Symlink { .. })
+                && file.last_update.operation != FileOperation::Remove
+        })
+        .filter_map(|file| normalize_remote_path(&file.path).ok())
+        .collect();
+    let is_unsafe_path = |path: &str| match normalize_remote_path(path) {
+        Ok(relative_path) => {
+            relative_path
+                .ancestors()
+                .skip(1)
+                .any(|folder| created_symlinks.contains(folder))
+                || destination.local_path(path).is_err()
+        }
+        Err(_) => true,
+    };
+    files
+        .iter()
+        .filter(|file| {
+            let is_unsafe_source = file.from_path.as_deref().is_some_and(is_unsafe_path);
+            let is_unsafe_kind = match &file.kind {
+                FileKind::HardLink { target } => is_unsafe_path(target),
+                // backups which follow symlinks never store one, and trust the ones they
+                // find, so a link created by the server could lead anywhere later on
+                FileKind::Symlink { .. } => destination.follow_symlinks,
+                _ => false,
+            };
+            is_unsafe_path(&file.path) || is_unsafe_source || is_unsafe_kind
+        })
+        .map(|file| file.path.to_owned())
+        .collect()
+}
+
+async fn apply_metadata(destination: Destination<'_>, file: &RSFile) -> Result<()> {
+    let Some(metadata) = file.metadata.clone() else {
+        return Ok(());
+    };
+    let path = destination.local_path(&file.path)?;
+    spawn_blocking(move || metadata.apply(&path)).await?
+}
+
+/// Deletes the removed files, along with the old copies of moved files which had
+/// to be downloaded again.
+async fn delete_removed_files(destination: Destination<'_>, files: &[RSFile]) -> Result<()> {
+    let removed_files: Vec<&RSFile> = files
         .iter()
         .filter(|f| f.last_update.operation == FileOperation::Remove)
-    {
-        let path = root.join(&file.path);
-        tokio::fs::remove_file(&path).await?;
+        .collect();
+    let mut removed_paths = Vec::new();
+    for file in removed_files.iter() {
+        let path = destination.local_path(&file.path)?;
+        if file.kind == FileKind::Directory {
+            // the folder stops being tracked once something is put inside of it
+            delete_folder_if_empty(&path).await?;
+        } else {
+            tokio::fs::remove_file(&path).await?;
+        }
+        removed_paths.push(path);
     }
-    delete_empty_folders(root).await
-}
-
-#[async_recursion]
-async fn delete_empty_folders(path: &Path) -> Result<()> {
-    if let Ok(mut entries) = tokio::fs::read_dir(path).await {
-        while let Some(entry) = entries.next_entry().await? {
-            let entry_path = entry.path();
-            if entry_path.is_dir() {
-                // Recursively delete empty subfolders
-                delete_empty_folders(&entry_path).await?;
-
-                // Delete current folder if it's empty
-                if tokio::fs::read_dir(&entry_path)
-                    .await?
-                    .next_entry()
-                    .await?
-                    .is_none()
-                {
-                    tokio::fs::remove_dir(&entry_path).await?;
-                }
-            }
+
+    let kept_paths: HashSet<&str> = files
+        .iter()
+        .filter(|f| f.last_update.operation != FileOperation::Remove)
+        .map(|f| f.path.as_str())
+        .collect();
+    let moved_from_paths = files
+        .iter()
+        .filter(|f| f.last_update.operation == FileOperation::Move)
+        .filter_map(|f| f.from_path.as_deref())
+        // a file may have been moved where another one was moved from
+        .filter(|from_path| !kept_paths.contains(from_path));
+    for from_path in moved_from_paths {
+        let path = destination.local_path(from_path)?;
+        match tokio::fs::remove_file(&path).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => removed_paths.push(path),
+        }
+    }
+
+    let kept_folders: HashSet<PathBuf> = files
+        .iter()
+        .filter(|f| {
+            f.kind == FileKind::Directory && f.last_update.operation != FileOperation::Remove
+        })
+        .map(|f| destination.local_path(&f.path))
+        .collect::<Result<_>>()?;
+    for path in removed_paths {
+        delete_emptied_parent_folders(destination.root, &path, &kept_folders).await?;
+    }
+    Ok(())
+}
+
+/// Deletes the parent folders that became empty because of removals in this update,
+/// stopping at the first one which still has content or is tracked as an empty folder.
+async fn delete_emptied_parent_folders(
+    root: &Path,
+    removed_path: &Path,
+    kept_folders: &HashSet<PathBuf>,
+) -> Result<()> {
+    for folder in removed_path
+        .ancestors()
+        .skip(1)
+        .take_while(|folder| *folder != root)
+    {
+        if kept_folders.contains(folder) || !delete_folder_if_empty(folder).await? {
+            break;
         }
     }
     Ok(())
 }
+
+async fn delete_folder_if_empty(path: &Path) -> Result<bool> {
+    let mut entries = match tokio::fs::read_dir(path).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    if entries.next_entry().await?.is_some() {
+        return Ok(false);
+    }
+    tokio::fs::remove_dir(path).await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashSet, VecDeque},
+        path::Path,
+    };
+
+    use redstone_common::{
+        model::{
+            api::{File as RSFile, FileOperation, FileUpdate},
+            fs_tree::FileKind,
+            tcp::{DownloadChunk, TcpMessageResponse, TcpMessageResponseStatus, UploadChunkAck},
+        },
+        testing::TempTree,
+        util::generate_digest_from_bytes,
+    };
+
+    use redstone_common::constants::TCP_FILE_CHUNK_SIZE;
+
+    use super::{
+        acknowledge_chunk, create_hard_link, delete_removed_files, find_unsafe_paths,
+        get_resumable_length, is_last_chunk, move_local_file, read_download_chunk, write_sparse,
+        Destination, SPARSE_BLOCK_SIZE,
+    };
+
+    fn remote_file(path: &str, kind: FileKind) -> RSFile {
+        RSFile {
+            id: path.into(),
+            path: path.into(),
+            digest: String::new(),
+            hash_algorithm: Default::default(),
+            last_update: FileUpdate {
+                operation: FileOperation::Add,
+            },
+            metadata: None,
+            kind,
+            from_path: None,
+        }
+    }
+
+    #[test]
+    fn finds_paths_through_symlinks_of_the_same_update() {
+        let destination = Destination {
+            root: Path::new("/backup"),
+            follow_symlinks: false,
+            followed_links: &HashSet::new(),
+        };
+        let link = FileKind::Symlink {
+            target: "/etc".into(),
+        };
+        let files = vec![
+            remote_file("link", link.clone()),
+            remote_file("link/passwd", FileKind::File),
+            remote_file("folder/file.txt", FileKind::File),
+        ];
+
+        assert_eq!(find_unsafe_paths(destination, &files), vec!["link/passwd"]);
+
+        let following = Destination {
+            follow_symlinks: true,
+            ..destination
+        };
+        assert_eq!(
+            find_unsafe_paths(following, &files[..1]),
+            vec!["link"],
+            "backups following symlinks never receive one"
+        );
+    }
+
+    fn response<T>(status: TcpMessageResponseStatus, data: Option<T>) -> TcpMessageResponse<T> {
+        TcpMessageResponse {
+            status,
+            data,
+            reason: None,
+            retry: None,
+        }
+    }
+
+    #[test]
+    fn acknowledges_chunks_out_of_order() {
+        let file = remote_file("file.txt", FileKind::File);
+        let ack = |file_id: &str, offset| {
+            Some(UploadChunkAck {
+                file_id: file_id.into(),
+                offset,
+            })
+        };
+        let mut chunks_in_flight = VecDeque::from([(0, 10), (10, 10), (20, 5)]);
+
+        let second = acknowledge_chunk(
+            &file,
+            &mut chunks_in_flight,
+            response(TcpMessageResponseStatus::Ok, ack("file.txt", 10)),
+        );
+        assert_eq!(second.unwrap(), (10, 10));
+        assert_eq!(chunks_in_flight, VecDeque::from([(0, 10), (20, 5)]));
+
+        // acks without data refer to the oldest chunk
+        let oldest = acknowledge_chunk(
+            &file,
+            &mut chunks_in_flight,
+            response(TcpMessageResponseStatus::Ok, None),
+        );
+        assert_eq!(oldest.unwrap(), (0, 10));
+
+        let unknown_offset = acknowledge_chunk(
+            &file,
+            &mut chunks_in_flight,
+            response(TcpMessageResponseStatus::Ok, ack("file.txt", 10)),
+        );
+        let other_file = acknowledge_chunk(
+            &file,
+            &mut chunks_in_flight,
+            response(TcpMessageResponseStatus::Ok, ack("other.txt", 20)),
+        );
+        assert!(unknown_offset.is_err());
+        assert!(other_file.is_err());
+        assert_eq!(chunks_in_flight, VecDeque::from([(20, 5)]));
+
+        // errors point at the chunk they were acknowledged for
+        let error = acknowledge_chunk(
+            &file,
+            &mut chunks_in_flight,
+            TcpMessageResponse {
+                reason: Some("disk full".into()),
+                ..response(TcpMessageResponseStatus::Error, ack("file.txt", 20))
+            },
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("file.txt at offset 20"), "{error}");
+        assert!(error.contains("disk full"), "{error}");
+        assert!(chunks_in_flight.is_empty());
+    }
+
+    #[test]
+    fn reads_download_chunks_up_to_the_last_one() {
+        let file = remote_file("file.bin", FileKind::File);
+        let total_size = TCP_FILE_CHUNK_SIZE * 2;
+        let chunk = |file_id: &str, offset, size, last_chunk| {
+            response(
+                TcpMessageResponseStatus::Ok,
+                Some(DownloadChunk {
+                    file_id: file_id.into(),
+                    offset,
+                    total_size,
+                    data: vec![1; size],
+                    last_chunk,
+                }),
+            )
+        };
+
+        let first = read_download_chunk(&file, 0, chunk("file.bin", 0, TCP_FILE_CHUNK_SIZE, false));
+        assert!(!is_last_chunk(&first.unwrap(), TCP_FILE_CHUNK_SIZE));
+        // files of whole chunks end on their last full chunk, flagged or not
+        for last_chunk in [true, false] {
+            let last = chunk(
+                "file.bin",
+                TCP_FILE_CHUNK_SIZE,
+                TCP_FILE_CHUNK_SIZE,
+                last_chunk,
+            );
+            let last = read_download_chunk(&file, TCP_FILE_CHUNK_SIZE, last).unwrap();
+            assert!(is_last_chunk(&last, total_size));
+        }
+        // an empty chunk past the end is the last one too, flagged or not
+        for last_chunk in [true, false] {
+            let empty_last = chunk("file.bin", total_size, 0, last_chunk);
+            let empty_last = read_download_chunk(&file, total_size, empty_last).unwrap();
+            assert!(is_last_chunk(&empty_last, total_size));
+        }
+        let empty_file = response(
+            TcpMessageResponseStatus::Ok,
+            Some(DownloadChunk {
+                file_id: "file.bin".into(),
+                offset: 0,
+                total_size: 0,
+                data: Vec::new(),
+                last_chunk: false,
+            }),
+        );
+        let empty_file = read_download_chunk(&file, 0, empty_file).unwrap();
+        assert!(is_last_chunk(&empty_file, 0));
+
+        let empty = read_download_chunk(&file, 0, chunk("file.bin", 0, 0, false));
+        let wrong_offset =
+            read_download_chunk(&file, 0, chunk("file.bin", TCP_FILE_CHUNK_SIZE, 1, false));
+        let wrong_file = read_download_chunk(&file, 0, chunk("other.bin", 0, 1, false));
+        assert!(empty.is_err());
+        assert!(wrong_offset.is_err());
+        assert!(wrong_file.is_err());
+    }
+
+    #[test]
+    fn resumes_downloads_from_whole_chunks() {
+        assert_eq!(get_resumable_length(0), 0);
+        assert_eq!(get_resumable_length(TCP_FILE_CHUNK_SIZE - 1), 0);
+        assert_eq!(
+            get_resumable_length(TCP_FILE_CHUNK_SIZE),
+            TCP_FILE_CHUNK_SIZE
+        );
+        assert_eq!(
+            get_resumable_length(TCP_FILE_CHUNK_SIZE * 2 + 5),
+            TCP_FILE_CHUNK_SIZE * 2
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_zero_blocks_as_holes_which_read_back_as_zeros() {
+        let tree = TempTree::new("sparse");
+        let path = tree.path("sparse");
+        // a partial zero block is written, whole ones are skipped, trailing one included
+        let data = [
+            vec![1; 10],
+            vec![0; SPARSE_BLOCK_SIZE * 2 - 10],
+            vec![2; 5],
+            vec![0; SPARSE_BLOCK_SIZE * 2 - 5],
+        ]
+        .concat();
+        let mut file = tokio::fs::File::create(&path).await.unwrap();
+        write_sparse(&mut file, &data).await.unwrap();
+        // like downloads do, as a trailing hole only counts once the length is set
+        file.set_len(data.len() as u64).await.unwrap();
+        drop(file);
+
+        let written = std::fs::read(&path);
+
+        let written = written.unwrap();
+        assert_eq!(written.len(), data.len());
+        assert!(written == data);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn restores_hard_links_sharing_the_same_file() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tree = TempTree::new("restored-hard-links");
+        tree.write("target.txt", "content");
+        tree.write("folder/link.txt", "previous content");
+        let destination = Destination {
+            root: &tree.root,
+            follow_symlinks: false,
+            followed_links: &HashSet::new(),
+        };
+        let link = remote_file(
+            "folder/link.txt",
+            FileKind::HardLink {
+                target: "target.txt".into(),
+            },
+        );
+
+        let created = create_hard_link(destination, &link, "target.txt").await;
+        let target = std::fs::metadata(tree.path("target.txt")).unwrap();
+        let link = std::fs::metadata(tree.path("folder/link.txt")).unwrap();
+        let content = std::fs::read_to_string(tree.path("folder/link.txt"));
+
+        created.unwrap();
+        assert_eq!(link.ino(), target.ino());
+        assert_eq!(link.nlink(), 2);
+        assert_eq!(content.unwrap(), "content");
+    }
+
+    #[tokio::test]
+    async fn moves_local_copies_of_moved_files() {
+        let tree = TempTree::new("moves");
+        tree.write("old/kept.txt", "content");
+        tree.write("old/changed.txt", "changed locally");
+        let destination = Destination {
+            root: &tree.root,
+            follow_symlinks: false,
+            followed_links: &HashSet::new(),
+        };
+        let moved_file = |from_path: &str, path: &str| RSFile {
+            digest: generate_digest_from_bytes(b"content", Default::default()),
+            last_update: FileUpdate {
+                operation: FileOperation::Move,
+            },
+            from_path: Some(from_path.into()),
+            ..remote_file(path, FileKind::File)
+        };
+        let files = vec![
+            moved_file("old/kept.txt", "new/kept.txt"),
+            moved_file("old/changed.txt", "new/changed.txt"),
+        ];
+
+        let moved = move_local_file(destination, &files[0]).await.unwrap();
+        let changed_moved = move_local_file(destination, &files[1]).await.unwrap();
+        // the changed file would be downloaded, leaving its old copy behind
+        delete_removed_files(destination, &files).await.unwrap();
+        let new_content = std::fs::read_to_string(tree.path("new/kept.txt"));
+        let old_folder_exists = tree.path("old").exists();
+
+        assert!(moved);
+        assert!(!changed_moved);
+        assert_eq!(new_content.unwrap(), "content");
+        assert!(!old_folder_exists);
+    }
+}
@@ -1 +1,2 @@
 pub mod file_transfer;
+pub mod recovery;
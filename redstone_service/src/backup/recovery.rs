@@ -0,0 +1,206 @@
+use std::path::Path;
+
+use redstone_common::model::{
+    api::{FileUploadRequest, Update, UploadResponse},
+    backup::{get_index_file_for_path, IndexFile},
+    journal::{get_journal_file_for_path, JournalEntry},
+    Result,
+};
+
+use super::file_transfer::abort_upload;
+
+/// Settles a push which was interrupted before its index could be saved.
+///
+/// A push which the server committed is indexed as if it had finished. When nothing
+/// was committed, the server can drop what it received. Otherwise someone else pushed,
+/// and the next push asks for a pull first, which brings the files back in sync.
+/// Interrupted pulls and clones are left in the journal, as only redoing them settles them.
+pub async fn reconcile_interrupted_push(
+    root: &Path,
+    index_file: &mut IndexFile,
+    latest_update: &Update,
+) -> Result<()> {
+    if index_committed_push(root, index_file, latest_update)? {
+        return Ok(());
+    }
+    let journal_file_path = get_journal_file_for_path(root);
+    let Ok(Some(JournalEntry::Push {
+        base_update_id,
+        upload,
+        ..
+    })) = JournalEntry::from_file(&journal_file_path)
+    else {
+        return Ok(());
+    };
+    if latest_update.id == base_update_id {
+        // nothing was committed, so the server can drop what it received
+        let _ = abort_upload(&upload.upload_token).await;
+    }
+    let _ = JournalEntry::clear(&journal_file_path);
+    Ok(())
+}
+
+/// Saves the index of an interrupted push which the server committed, that is when
+/// its update is the latest one, returning whether there was one.
+pub fn index_committed_push(
+    root: &Path,
+    index_file: &mut IndexFile,
+    latest_update: &Update,
+) -> Result<bool> {
+    let journal_file_path = get_journal_file_for_path(root);
+    let Ok(Some(JournalEntry::Push {
+        upload, fs_tree, ..
+    })) = JournalEntry::from_file(&journal_file_path)
+    else {
+        return Ok(false);
+    };
+    if upload.update.id != latest_update.id {
+        return Ok(false);
+    }
+    index_file.backup = upload.backup;
+    index_file.current_update = latest_update.clone();
+    index_file.latest_update = latest_update.clone();
+    index_file.last_fs_tree = *fs_tree;
+    index_file.save(&get_index_file_for_path(root))?;
+    JournalEntry::clear(&journal_file_path)?;
+    Ok(true)
+}
+
+/// Finds the upload of an interrupted push which can be continued by sending `files`,
+/// that is when it declared exactly the same files and nothing was committed since.
+pub fn find_resumable_upload(
+    root: &Path,
+    latest_update: &Update,
+    files: &[FileUploadRequest],
+) -> Option<UploadResponse> {
+    match JournalEntry::from_file(&get_journal_file_for_path(root)) {
+        Ok(Some(JournalEntry::Push {
+            base_update_id,
+            files: interrupted_files,
+            upload,
+            ..
+        })) if base_update_id == latest_update.id && interrupted_files == files => Some(*upload),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use redstone_common::model::{
+        api::{Backup, FileOperation, FileUploadRequest, Update, UploadResponse},
+        backup::{get_index_file_for_path, BackupConfig, IndexFile},
+        fs_tree::{FSTree, RSFile},
+        journal::{get_journal_file_for_path, JournalEntry},
+    };
+    use redstone_common::testing::TempTree;
+
+    use super::{find_resumable_upload, reconcile_interrupted_push};
+
+    fn update(id: &str) -> Update {
+        Update {
+            id: id.into(),
+            hash: String::new(),
+            message: String::new(),
+        }
+    }
+
+    fn backup() -> Backup {
+        Backup {
+            id: "backup".into(),
+            name: "backup".into(),
+            entrypoint: String::new(),
+            hash_algorithm: Default::default(),
+        }
+    }
+
+    fn fs_tree(tree: &TempTree, files: &[&str]) -> FSTree {
+        FSTree {
+            files: files
+                .iter()
+                .map(|path| RSFile::new(path.to_string(), "digest".into(), 1))
+                .collect(),
+            root: tree.root.clone(),
+            hash_algorithm: Default::default(),
+            excluded: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn resumes_uploads_of_the_same_files_on_the_same_update() {
+        let tree = TempTree::new("recovery");
+        let file = |path: &str| {
+            FileUploadRequest::new(path.into(), Some("digest".into()), FileOperation::Add, 1)
+        };
+        let files = vec![file("a.txt"), file("b.txt")];
+        JournalEntry::Push {
+            base_update_id: "base".into(),
+            files: files.clone(),
+            upload: Box::new(UploadResponse {
+                backup: backup(),
+                files: vec![],
+                update: update("new"),
+                upload_token: "token".into(),
+            }),
+            fs_tree: Box::new(fs_tree(&tree, &["a.txt", "b.txt"])),
+        }
+        .save(&get_journal_file_for_path(&tree.root))
+        .unwrap();
+
+        let resumed = find_resumable_upload(&tree.root, &update("base"), &files);
+        let after_another_push = find_resumable_upload(&tree.root, &update("other"), &files);
+        let with_fewer_files = find_resumable_upload(&tree.root, &update("base"), &files[..1]);
+        let changed_file = FileUploadRequest {
+            digest: Some("changed".into()),
+            ..file("b.txt")
+        };
+        let with_changed_file =
+            find_resumable_upload(&tree.root, &update("base"), &[file("a.txt"), changed_file]);
+
+        assert_eq!(resumed.unwrap().upload_token, "token");
+        assert!(after_another_push.is_none());
+        assert!(with_fewer_files.is_none());
+        assert!(with_changed_file.is_none());
+    }
+
+    #[tokio::test]
+    async fn indexes_pushes_committed_before_being_indexed() {
+        let tree = TempTree::new("committed-push");
+        let index_file_path = get_index_file_for_path(&tree.root);
+        tree.create_dir(".rs");
+        let mut index_file = IndexFile::new(
+            backup(),
+            update("base"),
+            update("base"),
+            BackupConfig::default(),
+            fs_tree(&tree, &["a.txt"]),
+        );
+        index_file.save(&index_file_path).unwrap();
+        JournalEntry::Push {
+            base_update_id: "base".into(),
+            files: vec![],
+            upload: Box::new(UploadResponse {
+                backup: backup(),
+                files: vec![],
+                update: update("pushed"),
+                upload_token: "token".into(),
+            }),
+            fs_tree: Box::new(fs_tree(&tree, &["a.txt", "b.txt"])),
+        }
+        .save(&get_journal_file_for_path(&tree.root))
+        .unwrap();
+
+        reconcile_interrupted_push(&tree.root, &mut index_file, &update("pushed"))
+            .await
+            .unwrap();
+        let saved_index_file = IndexFile::from_file(&index_file_path).unwrap();
+        let journal_entry = JournalEntry::from_file(&get_journal_file_for_path(&tree.root));
+
+        for index_file in [&index_file, &saved_index_file] {
+            assert_eq!(index_file.current_update.id, "pushed");
+            assert_eq!(index_file.latest_update.id, "pushed");
+            assert_eq!(index_file.last_fs_tree.files.len(), 2);
+        }
+        assert!(journal_entry.unwrap().is_none());
+    }
+}
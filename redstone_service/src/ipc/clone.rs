@@ -1,4 +1,4 @@
-use std::{borrow::BorrowMut, path::Path};
+use std::{borrow::BorrowMut, collections::HashSet, path::Path};
 
 use interprocess::local_socket::LocalSocketStream;
 
@@ -6,30 +6,32 @@ use redstone_common::{
     model::{
         api::{CloneRequest as ApiCloneRequest, DownloadResponse, Endpoints, File},
         backup::{get_index_file_for_path, BackupConfig, IndexFile},
-        fs_tree::{FSTree, RSFile},
+        fs_tree::{FSTree, RSFile, ScanOptions},
         ipc::{
             clone::CloneRequest, ConfirmationRequest, FileActionProgress, IpcMessage,
             IpcMessageResponse,
         },
+        journal::{get_journal_file_for_path, JournalEntry},
         Result,
     },
     util::bytes_to_human_readable,
     web::api::{handle_response, RedstoneClient},
 };
 use reqwest::Method;
-use tokio::{io::AsyncWriteExt, sync::mpsc};
+use tokio::sync::mpsc;
 
 use crate::{
-    backup::file_transfer::download_files,
+    backup::file_transfer::{download_files, Destination},
     ipc::{prompt_action_confirmation, send_progress},
 };
 
-use super::build_fs_tree_with_progress;
+use super::{build_fs_tree_with_progress, lock_backup};
 
 pub async fn handle_clone_msg(
     connection: &mut LocalSocketStream,
     clone_request: &mut CloneRequest,
 ) -> Result<IpcMessage> {
+    let _lock = lock_backup(&clone_request.path, "clone", clone_request.wait).await?;
     let client = RedstoneClient::new();
     let request = &Some(ApiCloneRequest::new(clone_request.backup_name.clone()));
     let response = client
@@ -53,23 +55,49 @@ pub async fn handle_clone_msg(
         .into());
     }
 
+    let journal_file_path = get_journal_file_for_path(&clone_request.path);
+    JournalEntry::Clone {
+        update_id: clone_response.update.id.to_owned(),
+    }
+    .save(&journal_file_path)?;
+
+    // the clone scans its files the same way as the machine which tracked the backup
+    let backup_config = BackupConfig::from_shared(
+        clone_response.config.clone().unwrap_or_default(),
+        clone_response.backup.hash_algorithm,
+    );
+    // nothing was scanned yet, so no symlink leads to the backup's files
+    let followed_links = HashSet::new();
     let (tx, mut rx) = mpsc::unbounded_channel::<FileActionProgress>();
     let (_, download_result) = tokio::join!(
         send_progress(connection.borrow_mut(), &mut rx),
         download_files(
-            clone_request.path.clone(),
+            Destination::new(&clone_request.path, backup_config.symlinks, &followed_links),
             &clone_response.files,
             clone_response.download_token.clone(),
             clone_response.total_bytes as u64,
+            clone_request.transfer,
             tx
         )
     );
 
     download_result?;
 
-    let fs_tree = build_fs_tree_with_progress(connection, clone_request.path.clone()).await?;
+    let fs_tree = build_fs_tree_with_progress(
+        connection,
+        clone_request.path.clone(),
+        backup_config.scan_options(),
+    )
+    .await?;
 
-    write_index_file(clone_request.borrow_mut(), &clone_response, fs_tree).await?;
+    write_index_file(
+        clone_request.borrow_mut(),
+        &clone_response,
+        backup_config,
+        fs_tree,
+    )
+    .await?;
+    JournalEntry::clear(&journal_file_path)?;
 
     Ok(IpcMessageResponse {
         message: None,
@@ -80,7 +108,7 @@ pub async fn handle_clone_msg(
 }
 
 fn get_conflicting_files(path: &Path, api_files: &[File]) -> Result<Vec<RSFile>> {
-    let fs_tree = FSTree::build(path.to_path_buf(), None)?;
+    let fs_tree = FSTree::build(path.to_path_buf(), &ScanOptions::default(), None)?;
     let api_file_paths: Vec<String> = api_files.iter().map(|file| file.path.clone()).collect();
     Ok(fs_tree.get_conflicting_files(api_file_paths))
 }
@@ -103,9 +131,9 @@ fn get_confirmation_request_message(conflicting_files: Vec<RSFile>, total_bytes:
 async fn write_index_file(
     clone_request: &mut CloneRequest,
     clone_response: &DownloadResponse,
+    backup_config: BackupConfig,
     fs_tree: FSTree,
 ) -> Result<()> {
-    let backup_config = BackupConfig::new(None, false);
     let index_file = IndexFile::new(
         clone_response.backup.clone(),
         clone_response.update.clone(),
@@ -119,13 +147,5 @@ async fn write_index_file(
             tokio::fs::create_dir_all(&parent_folders).await?;
         }
     }
-    let mut file = tokio::fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&index_file_path)
-        .await?;
-
-    file.write_all(&bincode::serialize(&index_file)?).await?;
-    Ok(())
+    index_file.save(&index_file_path)
 }
@@ -1,11 +1,15 @@
-use std::{borrow::BorrowMut, path::PathBuf};
+use std::{
+    borrow::BorrowMut,
+    path::{Path, PathBuf},
+};
 
 use interprocess::local_socket::LocalSocketStream;
 use redstone_common::{
     constants::IPC_SOCKET_PATH,
     ipc::{receive, send, send_and_receive},
     model::{
-        fs_tree::FSTree,
+        backup_lock::BackupLock,
+        fs_tree::{FSTree, ScanOptions},
         ipc::{
             ConfirmationRequest, ConfirmationResponse, FileActionProgress, IpcMessage,
             IpcMessageRequest, IpcMessageRequestType,
@@ -101,15 +105,25 @@ pub fn progress_sender_factory(
 pub async fn build_fs_tree_with_progress(
     connection: &mut LocalSocketStream,
     root: PathBuf,
+    options: ScanOptions,
 ) -> Result<FSTree> {
     let (tx, mut rx) = unbounded_channel::<FileActionProgress>();
     let (send_progress_result, fs_tree) = tokio::join!(
         send_progress(connection.borrow_mut(), &mut rx),
         spawn_blocking(move || {
             let progress_sender_factory = &progress_sender_factory(&tx);
-            FSTree::build(root, Some(progress_sender_factory))
+            FSTree::build(root, &options, Some(progress_sender_factory))
         })
     );
     send_progress_result?;
     fs_tree?
 }
+
+/// Locks the backup for the operation, waiting until it's free when asked to.
+pub async fn lock_backup(root: &Path, operation: &str, wait: bool) -> Result<BackupLock> {
+    if !wait {
+        return BackupLock::try_acquire(root, operation);
+    }
+    let (root, operation) = (root.to_path_buf(), operation.to_owned());
+    spawn_blocking(move || BackupLock::acquire(&root, &operation)).await?
+}
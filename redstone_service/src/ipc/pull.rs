@@ -9,6 +9,7 @@ use redstone_common::{
             pull::PullRequest, ConfirmationRequest, FileActionProgress, IpcMessage,
             IpcMessageResponse,
         },
+        journal::{get_journal_file_for_path, JournalEntry},
         DomainError, RedstoneError, Result,
     },
     util::bytes_to_human_readable,
@@ -18,21 +19,28 @@ use reqwest::Method;
 use tokio::sync::mpsc;
 
 use crate::{
-    api::update::check_latest_update, backup::file_transfer::download_files, ipc::send_progress,
+    api::update::check_latest_update,
+    backup::{
+        file_transfer::{download_files, Destination},
+        recovery::reconcile_interrupted_push,
+    },
+    ipc::send_progress,
 };
 
-use super::{build_fs_tree_with_progress, prompt_action_confirmation};
+use super::{build_fs_tree_with_progress, lock_backup, prompt_action_confirmation};
 
 pub async fn handle_pull_msg(
     connection: &mut LocalSocketStream,
     pull_request: &mut PullRequest,
 ) -> Result<IpcMessage> {
+    let _lock = lock_backup(&pull_request.path, "pull", pull_request.wait).await?;
     let index_file_path = get_index_file_for_path(&pull_request.path);
     let mut index_file = IndexFile::from_file(&index_file_path)?;
 
     let latest_update = check_latest_update(index_file.backup.id.to_owned()).await?;
     index_file.latest_update = latest_update.clone();
     index_file.save(&index_file_path)?;
+    reconcile_interrupted_push(&pull_request.path, &mut index_file, &latest_update).await?;
     if latest_update.hash == index_file.current_update.hash {
         return wrap(IpcMessageResponse {
             message: None,
@@ -69,24 +77,44 @@ pub async fn handle_pull_msg(
         .into());
     }
 
+    let journal_file_path = get_journal_file_for_path(&pull_request.path);
+    JournalEntry::Pull {
+        update_id: download_response.update.id.to_owned(),
+    }
+    .save(&journal_file_path)?;
+
+    let followed_links = index_file.last_fs_tree.followed_links();
     let (tx, mut rx) = mpsc::unbounded_channel::<FileActionProgress>();
 
-    let (_, _) = tokio::join!(
+    let (_, download_result) = tokio::join!(
         send_progress(connection.borrow_mut(), &mut rx),
         download_files(
-            pull_request.path.clone(),
+            Destination::new(
+                &pull_request.path,
+                index_file.config.symlinks,
+                &followed_links
+            ),
             &download_response.files,
             download_response.download_token.to_owned(),
             download_response.total_bytes as u64,
+            pull_request.transfer,
             tx
         )
     );
+    download_result?;
 
     index_file.current_update = download_response.update.clone();
-    index_file.last_fs_tree =
-        build_fs_tree_with_progress(connection, pull_request.path.clone()).await?;
+    let mut fs_tree = build_fs_tree_with_progress(
+        connection,
+        pull_request.path.clone(),
+        index_file.config.scan_options(),
+    )
+    .await?;
+    fs_tree.keep_unscanned_files(&index_file.last_fs_tree);
+    index_file.last_fs_tree = fs_tree;
 
     index_file.save(&index_file_path)?;
+    JournalEntry::clear(&journal_file_path)?;
 
     wrap(IpcMessageResponse {
         message: None,
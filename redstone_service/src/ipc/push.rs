@@ -9,6 +9,7 @@ use redstone_common::{
             push::PushRequest as IpcPushRequest, ConfirmationRequest, FileActionProgress,
             IpcMessage, IpcMessageResponse,
         },
+        journal::{get_journal_file_for_path, JournalEntry},
         DomainError, RedstoneError, Result,
     },
     web::api::{handle_response, RedstoneClient},
@@ -17,20 +18,27 @@ use reqwest::Method;
 use tokio::sync::mpsc;
 
 use crate::{
-    api::update::check_latest_update, backup::file_transfer::send_files, ipc::send_progress,
+    api::update::check_latest_update,
+    backup::{
+        file_transfer::send_files,
+        recovery::{find_resumable_upload, index_committed_push, reconcile_interrupted_push},
+    },
+    ipc::send_progress,
 };
 
-use super::{build_fs_tree_with_progress, prompt_action_confirmation};
+use super::{build_fs_tree_with_progress, lock_backup, prompt_action_confirmation};
 pub async fn handle_push_msg(
     connection: &mut LocalSocketStream,
     push_request: &mut IpcPushRequest,
 ) -> Result<IpcMessage> {
+    let _lock = lock_backup(&push_request.path, "push", push_request.wait).await?;
     let index_file_path = get_index_file_for_path(&push_request.path);
     let mut index_file = IndexFile::from_file(&index_file_path)?;
 
     let latest_update = check_latest_update(index_file.backup.id.to_owned()).await?;
     index_file.latest_update = latest_update.clone();
     index_file.save(&index_file_path)?;
+    index_committed_push(&push_request.path, &mut index_file, &latest_update)?;
 
     if latest_update.hash != index_file.current_update.hash {
         return wrap(IpcMessageResponse {
@@ -40,7 +48,11 @@ pub async fn handle_push_msg(
         });
     }
 
-    let fs_tree = build_fs_tree_with_progress(connection, push_request.path.clone()).await?;
+    let mut scan_options = index_file.config.scan_options();
+    scan_options.paranoid = push_request.paranoid;
+    let mut fs_tree =
+        build_fs_tree_with_progress(connection, push_request.path.clone(), scan_options).await?;
+    fs_tree.keep_unscanned_files(&index_file.last_fs_tree);
     let diff = fs_tree.diff(&index_file.last_fs_tree)?;
     let total_size = diff.total_size();
     if !diff.has_changes() {
@@ -52,8 +64,10 @@ pub async fn handle_push_msg(
     }
     let confirmation_request = ConfirmationRequest {
         message: format!(
-            "The following changes will be made:\n{}",
-            diff.get_changes_message()
+            "The following changes will be made:\n{}{}{}",
+            diff.get_changes_message(),
+            fs_tree.get_excluded_message(),
+            fs_tree.get_skipped_message()
         ),
     };
     let confirmation_result =
@@ -69,24 +83,48 @@ pub async fn handle_push_msg(
     let request = ApiPushRequest {
         backup_id: index_file.backup.id.to_owned(),
         files: FileUploadRequest::from_diff(&diff),
+        hash_algorithm: fs_tree.hash_algorithm,
     };
-    let client = RedstoneClient::new();
-    let res = client
-        .send(Method::POST, Endpoints::Push.get_url()?, &Some(request))
-        .await?;
-
-    let push_response: UploadResponse = handle_response(res).await?;
+    let resumed_upload = find_resumable_upload(&push_request.path, &latest_update, &request.files);
+    let resume = resumed_upload.is_some();
+    let push_response: UploadResponse = match resumed_upload {
+        Some(upload) => upload,
+        None => {
+            reconcile_interrupted_push(&push_request.path, &mut index_file, &latest_update).await?;
+            let client = RedstoneClient::new();
+            let res = client
+                .send(Method::POST, Endpoints::Push.get_url()?, &Some(&request))
+                .await?;
+            handle_response(res).await?
+        }
+    };
+    let journal_file_path = get_journal_file_for_path(&push_request.path);
+    JournalEntry::Push {
+        base_update_id: index_file.current_update.id.to_owned(),
+        files: request.files,
+        upload: Box::new(push_response.clone()),
+        fs_tree: Box::new(fs_tree.clone()),
+    }
+    .save(&journal_file_path)?;
     let (tx, mut rx) = mpsc::unbounded_channel::<FileActionProgress>();
     let (_, send_files_result) = tokio::join!(
         send_progress(connection.borrow_mut(), &mut rx),
         send_files(
             &push_response.files,
             &push_response.upload_token,
-            push_request.path.clone(),
+            &fs_tree,
             total_size,
+            push_request.transfer,
+            resume,
             tx,
         )
     );
+    if let Err(RedstoneError::DomainError(DomainError::FileChangedDuringBackup(_))) =
+        &send_files_result
+    {
+        // the upload was aborted, so there's nothing left to resume
+        JournalEntry::clear(&journal_file_path)?;
+    }
     send_files_result?;
 
     let latest_update = check_latest_update(index_file.backup.id.to_owned()).await?;
@@ -98,6 +136,7 @@ pub async fn handle_push_msg(
         fs_tree.clone(),
     );
     index_file.save(&index_file_path)?;
+    JournalEntry::clear(&journal_file_path)?;
 
     wrap(IpcMessageResponse {
         message: None,
@@ -11,21 +11,22 @@ use redstone_common::{
     web::api::{handle_response, RedstoneClient},
 };
 use reqwest::Method;
-use std::{borrow::BorrowMut, io::Write, path::PathBuf};
+use std::{borrow::BorrowMut, path::Path};
 use tokio::sync::mpsc;
 
 use crate::{backup::file_transfer::send_files, ipc::send_progress};
 
-use super::{build_fs_tree_with_progress, prompt_action_confirmation};
+use super::{build_fs_tree_with_progress, lock_backup, prompt_action_confirmation};
 
 pub async fn handle_track_msg(
     connection: &mut LocalSocketStream,
     track_request: &mut TrackRequest,
 ) -> Result<IpcMessage> {
     let base_path = &track_request.base_path;
+    let _lock = lock_backup(base_path, "track", track_request.wait).await?;
     let index_file_path = get_index_file_for_path(base_path);
     if index_file_path.exists() {
-        let path = base_path.to_str().unwrap().into();
+        let path = base_path.to_string_lossy().into();
         return wrap(IpcMessageResponse {
             keep_connection: false,
             error: Some(RedstoneError::DomainError(
@@ -34,7 +35,24 @@ pub async fn handle_track_msg(
             message: None,
         });
     }
-    let fs_tree = build_fs_tree_with_progress(connection, track_request.base_path.clone()).await?;
+    let config = BackupConfig {
+        hashing_threads: track_request.hashing_threads,
+        preserve_ownership: track_request.preserve_ownership,
+        preserve_xattrs: track_request.preserve_xattrs,
+        symlinks: track_request.symlinks,
+        hash_algorithm: track_request.hash_algorithm,
+        exclusions: track_request.exclusions.clone(),
+        git_ignore: track_request.git_ignore,
+        include: track_request.include.clone(),
+        strict: track_request.strict,
+        ..BackupConfig::new(track_request.sync_every.clone(), track_request.watch)
+    };
+    let fs_tree = build_fs_tree_with_progress(
+        connection,
+        track_request.base_path.clone(),
+        config.scan_options(),
+    )
+    .await?;
     let confirmation_request = get_confirmation_message(&fs_tree);
     let confirmation_result =
         prompt_action_confirmation(connection.borrow_mut(), confirmation_request).await?;
@@ -47,15 +65,19 @@ pub async fn handle_track_msg(
     }
 
     let total_size = fs_tree.total_size();
-    let root_folder = fs_tree.root.clone();
     let files = fs_tree
         .files
         .iter()
         .map(|file| FileUploadRequest::from(file.clone()))
         .collect();
 
-    let declare_request =
-        DeclareBackupRequest::new(track_request.name.as_str(), fs_tree.root.clone(), files);
+    let declare_request = DeclareBackupRequest::new(
+        track_request.name.as_str(),
+        fs_tree.root.clone(),
+        files,
+        fs_tree.hash_algorithm,
+        config.shared(),
+    );
 
     let declare_response = declare(&declare_request).await?;
     let (tx, mut rx) = mpsc::unbounded_channel::<FileActionProgress>();
@@ -64,19 +86,16 @@ pub async fn handle_track_msg(
         send_files(
             &declare_response.files,
             &declare_response.upload_token,
-            root_folder,
+            &fs_tree,
             total_size,
+            track_request.transfer,
+            false,
             tx
         )
     );
     send_files_result?;
 
-    create_files(
-        &index_file_path,
-        declare_response,
-        track_request.borrow_mut(),
-        fs_tree,
-    )?;
+    create_files(&index_file_path, declare_response, config, fs_tree)?;
     wrap(IpcMessageResponse {
         keep_connection: false,
         error: None,
@@ -104,32 +123,32 @@ fn get_confirmation_message(fs_tree: &FSTree) -> ConfirmationRequest {
     };
 
     let message = format!(
-        "By continuing, you will recursively backup the following:\n{}",
-        diff.get_changes_message()
+        "By continuing, you will recursively backup the following:\n{}{}{}",
+        diff.get_changes_message(),
+        fs_tree.get_excluded_message(),
+        fs_tree.get_skipped_message()
     );
 
     ConfirmationRequest { message }
 }
 
 fn create_files(
-    index_file_path: &PathBuf,
+    index_file_path: &Path,
     declare_response: UploadResponse,
-    track_request: &mut TrackRequest,
+    config: BackupConfig,
     fs_tree: FSTree,
 ) -> Result<IndexFile> {
     let parent_folders = index_file_path.parent();
     if let Some(folder_path) = parent_folders {
         std::fs::create_dir_all(folder_path)?;
     }
-    let mut index_file = std::fs::File::create(index_file_path)?;
-    let config = BackupConfig::new(track_request.sync_every.clone(), track_request.watch);
-    let index_file_content = IndexFile::new(
+    let index_file = IndexFile::new(
         declare_response.backup,
         declare_response.update.clone(),
         declare_response.update,
         config,
         fs_tree,
     );
-    index_file.write_all(&bincode::serialize(&index_file_content)?)?;
-    Ok(index_file_content)
+    index_file.save(index_file_path)?;
+    Ok(index_file)
 }
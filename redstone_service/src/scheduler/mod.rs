@@ -15,7 +15,6 @@ pub async fn run_upload_jobs(new_job_receiver: &mut UnboundedReceiver<UpdateJob>
 
 #[derive(Debug)]
 pub struct UpdateJob {
-    pub backup_id: usize,
     pub cron_expr: String,
 }
 
@@ -23,11 +22,9 @@ fn get_jobs() -> Vec<UpdateJob> {
     vec![
         UpdateJob {
             cron_expr: String::from("*/3 * * * * * *"),
-            backup_id: 1,
         },
         UpdateJob {
             cron_expr: String::from("*/6 * * * * * *"),
-            backup_id: 2,
         },
     ]
 }